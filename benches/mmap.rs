@@ -0,0 +1,40 @@
+// Compares png_to_ico, which reads its input onto the heap, with png_to_ico_mapped on inputs from 4 KiB to 4 MiB.
+// Run with `cargo bench --features mmap,testing --bench mmap`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use iconifier::png::testing::{insert_chunk, rgba_png};
+use iconifier::png::{png_to_ico, png_to_ico_mapped};
+
+const SIZES: [usize; 6] = [4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20];
+
+// A 16x16 RGBA PNG padded to `size` bytes with a private ancillary chunk before IEND, which is embedded with the rest.
+fn padded_png(size: usize) -> Vec<u8> {
+	let png = rgba_png(16, 16, |_, _| [200; 4]);
+	let padding = size.saturating_sub(png.len() + 12);
+	insert_chunk(&png, b"prVt", &vec![0x5A; padding])
+}
+
+fn read_vs_mapped(c: &mut Criterion) {
+	let dir = std::env::temp_dir();
+	let mut group = c.benchmark_group("png_to_ico");
+	for size in SIZES {
+		let input = dir.join(format!("iconifier_mmap_{}.png", size));
+		let output = dir.join(format!("iconifier_mmap_{}.ico", size));
+		std::fs::write(&input, padded_png(size)).expect("the temporary directory is writable");
+		group.throughput(Throughput::Bytes(size as u64));
+		group.bench_with_input(BenchmarkId::new("read", size), &input, |b, input| {
+			b.iter(|| png_to_ico(black_box(input), &output).unwrap())
+		});
+		group.bench_with_input(BenchmarkId::new("mapped", size), &input, |b, input| {
+			b.iter(|| png_to_ico_mapped(black_box(input), &output).unwrap())
+		});
+		let _ = std::fs::remove_file(&input);
+		let _ = std::fs::remove_file(&output);
+	}
+	group.finish();
+}
+
+criterion_group!(benches, read_vs_mapped);
+criterion_main!(benches);
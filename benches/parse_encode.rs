@@ -0,0 +1,61 @@
+// Times the parse and encode paths through the public API on generated 32bpp sources of 16, 64 and 256 pixels.
+// Run with `cargo bench --features testing`, optionally followed by `-- <filter>` to run only the benchmarks whose names match it.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use iconifier::png::testing::rgba_png;
+use iconifier::png::{encode_ico_from_bytes, is_png, png_to_ico, BmpOptions, EntryFormat, PngParser};
+
+const SIZES: [(&str, u32); 3] = [("small", 16), ("medium", 64), ("large", 256)];
+
+// A PNG of a gradient with some per-pixel noise, so it doesn't compress unrealistically well.
+fn source_png(size: u32) -> Vec<u8> {
+	rgba_png(size, size, |x, y| {
+		let noise = ((y * size + x).wrapping_mul(2_654_435_761) >> 27) as u8;
+		[(x * 255 / size) as u8 ^ noise, (y * 255 / size) as u8, noise, 255 - (x + y) as u8 / 2]
+	})
+}
+
+fn parse(c: &mut Criterion) {
+	let parser = PngParser::new();
+	for (label, size) in SIZES {
+		let png = source_png(size);
+		c.bench_with_input(BenchmarkId::new("signature", label), &png, |b, png| b.iter(|| is_png(black_box(png))));
+		c.bench_with_input(BenchmarkId::new("chunks", label), &png, |b, png| {
+			b.iter(|| {
+				for chunk in parser.chunks(black_box(png)).unwrap() {
+					black_box(chunk.unwrap());
+				}
+			})
+		});
+		c.bench_with_input(BenchmarkId::new("header", label), &png, |b, png| {
+			b.iter(|| parser.parse_header_bytes(black_box(png)).unwrap())
+		});
+	}
+}
+
+fn encode(c: &mut Criterion) {
+	let dir = std::env::temp_dir();
+	for (label, size) in SIZES {
+		let png = source_png(size);
+		c.bench_with_input(BenchmarkId::new("encode_png", label), &png, |b, png| {
+			b.iter(|| encode_ico_from_bytes(&[black_box(png)], EntryFormat::Png).unwrap())
+		});
+		c.bench_with_input(BenchmarkId::new("encode_bmp", label), &png, |b, png| {
+			let format = EntryFormat::Bmp(BmpOptions::default());
+			b.iter(|| encode_ico_from_bytes(&[black_box(png)], format).unwrap())
+		});
+
+		// The file path, which reads the source once and writes the ICO.
+		let input = dir.join(format!("iconifier_bench_{}.png", size));
+		let output = dir.join(format!("iconifier_bench_{}.ico", size));
+		std::fs::write(&input, &png).expect("the temporary directory is writable");
+		c.bench_function(&format!("png_to_ico/{}", label), |b| b.iter(|| png_to_ico(&input, &output).unwrap()));
+		let _ = std::fs::remove_file(&input);
+		let _ = std::fs::remove_file(&output);
+	}
+}
+
+criterion_group!(benches, parse, encode);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+use std::{error::Error, result::Result};
+
+use iconifier::png::PngParser;
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let path = std::env::args().nth(1).ok_or("Usage: list_chunks <image.png>")?;
+	let data = std::fs::read(path)?;
+	for chunk in PngParser::new().chunks(&data)? {
+		let chunk = chunk?;
+		let crc = if chunk.crc_matches() { "" } else { " (CRC mismatch)" };
+		println!("{} {}{}", String::from_utf8_lossy(&chunk.name), chunk.declared_len, crc);
+	}
+	Ok(())
+}
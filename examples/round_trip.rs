@@ -0,0 +1,18 @@
+use std::{error::Error, result::Result};
+
+use iconifier::png::testing::{assert_round_trip, sample_pngs};
+
+// Round-trips the built-in samples, or the PNGs given as arguments.
+fn main() -> Result<(), Box<dyn Error>> {
+	let paths = std::env::args().skip(1).collect::<Vec<_>>();
+	let images = if paths.is_empty() {
+		sample_pngs()
+	} else {
+		paths.into_iter().map(|path| Ok((path.clone(), std::fs::read(path)?))).collect::<std::io::Result<_>>()?
+	};
+	for (name, png) in images {
+		assert_round_trip(&png);
+		println!("ok  {}", name);
+	}
+	Ok(())
+}
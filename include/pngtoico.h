@@ -0,0 +1,38 @@
+/* C interface to iconifier, available when the crate is built with the `ffi` feature. */
+#ifndef PNGTOICO_H
+#define PNGTOICO_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An argument was null or not valid UTF-8. */
+#define PNGTOICO_ERROR_INVALID_ARGUMENT (-1)
+/* The input file could not be read. */
+#define PNGTOICO_ERROR_UNREADABLE_INPUT (-2)
+/* The output file could not be written. */
+#define PNGTOICO_ERROR_UNWRITABLE_OUTPUT (-3)
+/* The input is not a valid image, or can't be stored in an ICO. */
+#define PNGTOICO_ERROR_INVALID_IMAGE (-4)
+/* The converter panicked. This is a bug in the library. */
+#define PNGTOICO_ERROR_INTERNAL (-5)
+
+/*
+ * Converts the PNG at `input` into an ICO at `output`, replacing the extension of `output` with .ico.
+ * Both paths are UTF-8 and only borrowed for the duration of the call.
+ * Returns 0 on success, or one of the negative PNGTOICO_ERROR_* codes.
+ */
+int pngtoico_convert(const char *input, const char *output);
+
+/*
+ * The message for the last failed call on this thread, or NULL if the last call succeeded.
+ * The string is owned by the library and must not be freed. It stays valid until the next call into the library
+ * on the same thread.
+ */
+const char *pngtoico_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,397 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{PngIcoError, Result};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+// The red, green and blue masks of 32bpp BGRA pixels, as stored little-endian after the header.
+const BGRA_MASKS: [u8; 12] = [0, 0, 0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0, 0, 0];
+
+pub(crate) const BMP_SIGNATURE: [u8; 2] = *b"BM";
+const BMP_FILE_HEADER_SIZE: usize = 14;
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+	u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+	u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn invalid(reason: &'static str) -> PngIcoError {
+	PngIcoError::InvalidBmp(reason)
+}
+
+// Bytes per row of a DIB, which are padded to a 4-byte boundary.
+pub(crate) fn dib_stride(width: u32, bits_per_pixel: u32) -> usize {
+	(width as usize * bits_per_pixel as usize).div_ceil(32) * 4
+}
+
+// Decodes a DIB that starts with a BITMAPINFOHEADER into 8-bit RGBA rows, top row first.
+// Icon DIBs store twice the real height, with a 1bpp AND mask following the color data.
+pub(crate) fn decode_dib(data: &[u8], icon: bool) -> Result<(u32, u32, Vec<u8>)> {
+	decode_dib_pixels(data, None, icon)
+}
+
+// Decodes a .bmp file: a BITMAPFILEHEADER, whose last field is the offset of the pixel data, followed by the DIB.
+pub(crate) fn decode_bmp(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+	if data.len() < BMP_FILE_HEADER_SIZE || !data.starts_with(&BMP_SIGNATURE) {
+		return Err(invalid("missing BITMAPFILEHEADER"));
+	}
+	let pixels_offset = (read_u32(data, 10) as usize)
+		.checked_sub(BMP_FILE_HEADER_SIZE)
+		.ok_or_else(|| invalid("pixel data overlaps the file header"))?;
+	decode_dib_pixels(&data[BMP_FILE_HEADER_SIZE..], Some(pixels_offset), false)
+}
+
+// Decodes a DIB whose pixels start at `pixels_offset`, or right after the header and color table if it's not given.
+fn decode_dib_pixels(data: &[u8], pixels_offset: Option<usize>, icon: bool) -> Result<(u32, u32, Vec<u8>)> {
+	if data.len() < 40 {
+		return Err(invalid("missing BITMAPINFOHEADER"));
+	}
+	let header_size = read_u32(data, 0) as usize;
+	let width = read_u32(data, 4) as i32;
+	let stored_height = read_u32(data, 8) as i32;
+	let bits_per_pixel = read_u16(data, 14) as u32;
+	let compression = read_u32(data, 16);
+	let colors_used = read_u32(data, 32) as usize;
+
+	if header_size < 40 || width <= 0 || stored_height == 0 {
+		return Err(invalid("bad header fields"));
+	}
+	// 32bpp bitmaps with an alpha channel are often stored as BI_BITFIELDS, with masks that match BI_RGB's layout.
+	// The masks end a BITMAPV3INFOHEADER or later, and follow a plain BITMAPINFOHEADER.
+	let bgra_bitfields = bits_per_pixel == 32
+		&& compression == BI_BITFIELDS
+		&& data.get(40..52).is_some_and(|masks| masks == BGRA_MASKS);
+	if compression != BI_RGB && !bgra_bitfields {
+		return Err(invalid("compressed bitmaps are not supported"));
+	}
+	let top_down = stored_height < 0;
+	let height = stored_height.unsigned_abs() / if icon { 2 } else { 1 };
+	let width = width as u32;
+
+	let palette_len = match bits_per_pixel {
+		1 | 4 | 8 if colors_used == 0 => 1 << bits_per_pixel,
+		1 | 4 | 8 => colors_used,
+		24 | 32 => 0,
+		_ => return Err(invalid("unsupported bit depth")),
+	};
+	let palette = data
+		.get(header_size..header_size + palette_len * 4)
+		.ok_or_else(|| invalid("palette runs past the end of the data"))?;
+	let masks_len = if bgra_bitfields && header_size == 40 { 12 } else { 0 };
+	let pixels_start = pixels_offset.unwrap_or(header_size + masks_len + palette_len * 4);
+	let stride = dib_stride(width, bits_per_pixel);
+	let pixels = data
+		.get(pixels_start..pixels_start + stride * height as usize)
+		.ok_or_else(|| invalid("pixel data runs past the end of the data"))?;
+	let mask_stride = dib_stride(width, 1);
+	let mask = if icon {
+		let mask_start = pixels_start + stride * height as usize;
+		data.get(mask_start..mask_start + mask_stride * height as usize)
+	} else {
+		None
+	};
+
+	let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+	for y in 0..height as usize {
+		let row = if top_down { y } else { height as usize - 1 - y };
+		let src = &pixels[row * stride..(row + 1) * stride];
+		for x in 0..width as usize {
+			let (b, g, r, a) = match bits_per_pixel {
+				32 => (src[x * 4], src[x * 4 + 1], src[x * 4 + 2], src[x * 4 + 3]),
+				24 => (src[x * 3], src[x * 3 + 1], src[x * 3 + 2], 255),
+				_ => {
+					let bit = x * bits_per_pixel as usize;
+					let shift = 8 - bits_per_pixel as usize - bit % 8;
+					let index = ((src[bit / 8] as u32 >> shift) & ((1 << bits_per_pixel) - 1)) as usize;
+					let color = palette
+						.get(index * 4..index * 4 + 3)
+						.ok_or_else(|| invalid("palette index out of range"))?;
+					(color[0], color[1], color[2], 255)
+				}
+			};
+			rgba.extend_from_slice(&[r, g, b, a]);
+		}
+	}
+
+	// 32bpp entries carry real alpha unless it was left zeroed, so the AND mask only applies otherwise.
+	// Zeroed alpha outside icons is just the reserved byte of BI_RGB, so those pixels are opaque.
+	let has_alpha = bits_per_pixel == 32 && rgba.chunks(4).any(|px| px[3] != 0);
+	if bits_per_pixel == 32 && !has_alpha && !icon {
+		rgba.chunks_mut(4).for_each(|px| px[3] = 255);
+	}
+	if let (Some(mask), false) = (mask, has_alpha) {
+		for y in 0..height as usize {
+			let row = if top_down { y } else { height as usize - 1 - y };
+			let src = &mask[row * mask_stride..(row + 1) * mask_stride];
+			for x in 0..width as usize {
+				let transparent = src[x / 8] & (0x80 >> (x % 8)) != 0;
+				rgba[(y * width as usize + x) * 4 + 3] = if transparent { 0 } else { 255 };
+			}
+		}
+	}
+	Ok((width, height, rgba))
+}
+
+// Packs a 1bpp AND mask, setting the bit of every pixel with alpha below `threshold`.
+// Bits run from the most significant end of each byte, and rows are bottom-up and padded to 32 bits like the color data.
+fn and_mask(rgba: &[u8], width: u32, height: u32, threshold: u8) -> Vec<u8> {
+	let stride = dib_stride(width, 1);
+	let mut mask = vec![0u8; stride * height as usize];
+	for y in 0..height as usize {
+		let row = &mut mask[(height as usize - 1 - y) * stride..][..stride];
+		for x in 0..width as usize {
+			if rgba[(y * width as usize + x) * 4 + 3] < threshold {
+				row[x / 8] |= 0x80 >> (x % 8);
+			}
+		}
+	}
+	mask
+}
+
+// Writes a BITMAPINFOHEADER for an icon, whose height covers both the color data and the AND mask.
+fn write_icon_header(out: &mut Vec<u8>, width: u32, height: u32, bits_per_pixel: u16, image_size: usize) {
+	out.extend_from_slice(&40u32.to_le_bytes()); // Header size
+	out.extend_from_slice(&(width as i32).to_le_bytes()); // Width
+	out.extend_from_slice(&(height as i32 * 2).to_le_bytes()); // Height of color data plus mask
+	out.extend_from_slice(&1u16.to_le_bytes()); // Planes
+	out.extend_from_slice(&bits_per_pixel.to_le_bytes()); // Bits per pixel
+	out.extend_from_slice(&BI_RGB.to_le_bytes()); // Compression
+	out.extend_from_slice(&(image_size as u32).to_le_bytes()); // Image size
+	// Resolution and palette counts are unused. A color count of 0 means a full 2^bpp color table.
+	out.extend_from_slice(&[0; 16]);
+}
+
+// Encodes 8-bit RGBA as a 32bpp icon DIB: a BITMAPINFOHEADER with doubled height, BGRA rows and the AND mask.
+// Icon DIBs are stored bottom-up, as Windows does not accept negative (top-down) heights for icons. With
+// `premultiply`, the colors are multiplied by alpha, rounded to the nearest value.
+pub(crate) fn encode_icon_dib(rgba: &[u8], width: u32, height: u32, mask_threshold: u8, premultiply: bool) -> Vec<u8> {
+	let mask = and_mask(rgba, width, height, mask_threshold);
+	let color_len = width as usize * height as usize * 4;
+
+	let mut out = Vec::with_capacity(40 + color_len + mask.len());
+	write_icon_header(&mut out, width, height, 32, color_len + mask.len());
+
+	for row in rgba.chunks(width as usize * 4).rev() {
+		for px in row.chunks(4) {
+			let alpha = px[3];
+			let channel = |value: u8| {
+				if premultiply {
+					((value as u32 * alpha as u32 + 127) / 255) as u8
+				} else {
+					value
+				}
+			};
+			out.extend_from_slice(&[channel(px[2]), channel(px[1]), channel(px[0]), alpha]);
+		}
+	}
+	out.extend_from_slice(&mask);
+	out
+}
+
+// Encodes palette indices as a 4bpp or 8bpp icon DIB with a full color table, packed bottom-up rows and the AND mask.
+// Pixels whose palette alpha is below `mask_threshold` are masked, and their index is pointed at a black entry so
+// that the XOR step leaves the background untouched.
+pub(crate) fn encode_paletted_icon_dib(
+	indices: &[u8],
+	width: u32,
+	height: u32,
+	palette: &[[u8; 4]],
+	bits_per_pixel: u16,
+	mask_threshold: u8,
+) -> Result<Vec<u8>> {
+	let table_len = 1usize << bits_per_pixel;
+	if palette.len() > table_len {
+		return Err(PngIcoError::PalettedBmp("the palette has too many colors for the bit depth"));
+	}
+	let mut palette = palette.to_vec();
+	let rgba = indices.iter().flat_map(|&index| palette[index as usize]).collect::<Vec<_>>();
+	let mask = and_mask(&rgba, width, height, mask_threshold);
+
+	let mut indices = indices.to_vec();
+	if rgba.chunks(4).any(|px| px[3] < mask_threshold) {
+		let black = match palette.iter().position(|color| color[..3] == [0, 0, 0]) {
+			Some(black) => black,
+			None if palette.len() < table_len => {
+				palette.push([0, 0, 0, 255]);
+				palette.len() - 1
+			}
+			None => return Err(PngIcoError::PalettedBmp("no palette entry is left for transparent pixels")),
+		};
+		for (index, px) in indices.iter_mut().zip(rgba.chunks(4)) {
+			if px[3] < mask_threshold {
+				*index = black as u8;
+			}
+		}
+	}
+
+	let stride = dib_stride(width, bits_per_pixel as u32);
+	let color_len = stride * height as usize;
+	let mut out = Vec::with_capacity(40 + table_len * 4 + color_len + mask.len());
+	write_icon_header(&mut out, width, height, bits_per_pixel, color_len + mask.len());
+	for i in 0..table_len {
+		let [r, g, b, _] = palette.get(i).copied().unwrap_or([0; 4]);
+		out.extend_from_slice(&[b, g, r, 0]);
+	}
+	let pixels_per_byte = 8 / bits_per_pixel as usize;
+	for row in indices.chunks(width as usize).rev() {
+		let mut packed = vec![0u8; stride];
+		for (x, &index) in row.iter().enumerate() {
+			// The leftmost pixel goes in the most significant bits.
+			let shift = 8 - bits_per_pixel as usize * (x % pixels_per_byte + 1);
+			packed[x / pixels_per_byte] |= index << shift;
+		}
+		out.extend_from_slice(&packed);
+	}
+	out.extend_from_slice(&mask);
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn writes_32bpp_icon_dibs() {
+		// Opaque red and transparent green over half-transparent blue and opaque white.
+		let rgba = [[255, 0, 0, 255], [0, 255, 0, 0], [0, 0, 255, 128], [255; 4]].concat();
+		let dib = encode_icon_dib(&rgba, 2, 2, 1, false);
+		assert_eq!(dib.len(), 40 + 2 * 2 * 4 + 2 * 4);
+		assert_eq!((read_u32(&dib, 0), read_u32(&dib, 4), read_u32(&dib, 8)), (40, 2, 4));
+		assert_eq!((read_u16(&dib, 12), read_u16(&dib, 14), read_u32(&dib, 16)), (1, 32, BI_RGB));
+		assert_eq!(read_u32(&dib, 20), 24);
+		assert_eq!(dib[24..40], [0; 16]);
+		// BGRA rows, bottom row first.
+		assert_eq!(dib[40..56], [255, 0, 0, 128, 255, 255, 255, 255, 0, 0, 255, 255, 0, 255, 0, 0]);
+		// Only the transparent green pixel is masked, in the second row of the mask.
+		assert_eq!(dib[56..], [0, 0, 0, 0, 0x40, 0, 0, 0]);
+		assert_eq!(decode_dib(&dib, true).unwrap(), (2, 2, rgba));
+	}
+
+	#[test]
+	fn premultiplies_colors_only_when_asked_to() {
+		let pixel = [200, 100, 50, 128];
+		assert_eq!(encode_icon_dib(&pixel, 1, 1, 1, false)[40..44], [50, 100, 200, 128]);
+		assert_eq!(encode_icon_dib(&pixel, 1, 1, 1, true)[40..44], [25, 50, 100, 128]);
+		assert!(!crate::png::BmpOptions::default().premultiply);
+	}
+
+	#[test]
+	fn masks_pixels_below_the_threshold() {
+		// 9 pixels take 2 bytes of mask per row, padded to 4, and rows are stored bottom-up.
+		let alphas = [[0, 255, 0, 255, 0, 255, 0, 255, 0], [127; 9], [128; 9]];
+		let rgba = alphas.iter().flatten().flat_map(|&alpha| [9, 9, 9, alpha]).collect::<Vec<_>>();
+		let mask = and_mask(&rgba, 9, 3, 128);
+		assert_eq!(mask, [0, 0, 0, 0, 0xFF, 0x80, 0, 0, 0xAA, 0x80, 0, 0]);
+		assert_eq!(and_mask(&rgba, 9, 3, 0), [0; 12]);
+		assert_eq!(and_mask(&rgba, 9, 3, 255)[..8], [0xFF, 0x80, 0, 0, 0xFF, 0x80, 0, 0]);
+
+		let dib = encode_icon_dib(&rgba, 9, 3, 128, false);
+		assert_eq!(dib[40 + 9 * 3 * 4..], mask);
+	}
+
+	// Sixteen grays, black first, with the last one transparent.
+	fn grays() -> Vec<[u8; 4]> {
+		(0..16).map(|i| [i * 17, i * 17, i * 17, if i == 15 { 0 } else { 255 }]).collect()
+	}
+
+	#[test]
+	fn writes_4bpp_icon_dibs() {
+		let indices = [0, 1, 2, 3, 15, 4, 5, 6, 7, 8];
+		let dib = encode_paletted_icon_dib(&indices, 5, 2, &grays(), 4, 1).unwrap();
+		// Five 4-bit pixels take 3 bytes per row, padded to 4, and so does each row of the mask.
+		assert_eq!(dib.len(), 40 + 16 * 4 + 2 * 4 + 2 * 4);
+		assert_eq!((read_u32(&dib, 4), read_u32(&dib, 8), read_u16(&dib, 14)), (5, 4, 4));
+		assert_eq!(read_u32(&dib, 20), 16);
+		let table = dib[40..104].chunks(4).map(|entry| entry.to_vec()).collect::<Vec<_>>();
+		assert_eq!(table[1], [17, 17, 17, 0]);
+		assert_eq!(table[15], [255, 255, 255, 0]);
+		// Bottom row first, leftmost pixel in the high nibble. The transparent pixel is drawn with the black entry.
+		assert_eq!(dib[104..112], [0x45, 0x67, 0x80, 0, 0x01, 0x23, 0x00, 0]);
+		assert_eq!(dib[112..], [0, 0, 0, 0, 0x08, 0, 0, 0]);
+		let rgba = decode_dib(&dib, true).unwrap().2;
+		assert_eq!((rgba[4..8].to_vec(), rgba[16..20].to_vec()), (vec![17, 17, 17, 255], vec![0; 4]));
+	}
+
+	#[test]
+	fn writes_8bpp_icon_dibs() {
+		let dib = encode_paletted_icon_dib(&[1, 2, 3], 3, 1, &grays(), 8, 1).unwrap();
+		assert_eq!(read_u16(&dib, 14), 8);
+		let pixels = 40 + 256 * 4;
+		assert_eq!(dib[pixels - 4..pixels], [0; 4]);
+		assert_eq!(dib[pixels..], [1, 2, 3, 0, 0, 0, 0, 0]);
+		let rgba = decode_dib(&dib, true).unwrap().2;
+		assert_eq!(rgba, [[17, 17, 17, 255], [34, 34, 34, 255], [51, 51, 51, 255]].concat());
+	}
+
+	#[test]
+	fn rejects_palettes_that_do_not_fit() {
+		let mut palette = grays();
+		palette.push([1, 1, 1, 255]);
+		let err = encode_paletted_icon_dib(&[16], 1, 1, &palette, 4, 1).unwrap_err();
+		assert!(matches!(err, PngIcoError::PalettedBmp(_)));
+		// Without a black entry or room for one, masked pixels have nothing to be drawn with.
+		let no_black = grays().into_iter().map(|[r, g, b, a]| [r, g, b.max(1), a]).collect::<Vec<_>>();
+		let err = encode_paletted_icon_dib(&[15], 1, 1, &no_black, 4, 1).unwrap_err();
+		assert!(matches!(err, PngIcoError::PalettedBmp(_)));
+	}
+
+	// A .bmp file of 24 or 32bpp BGR(A) rows, stored top-down if `top_down` is set and bottom-up otherwise.
+	fn bmp_file(width: u32, height: u32, bits_per_pixel: u16, top_down: bool, rgba: &[u8]) -> Vec<u8> {
+		let stride = dib_stride(width, bits_per_pixel as u32);
+		let mut pixels = Vec::new();
+		let rows = rgba.chunks(width as usize * 4).collect::<Vec<_>>();
+		for y in 0..height as usize {
+			let row = rows[if top_down { y } else { height as usize - 1 - y }];
+			let start = pixels.len();
+			for px in row.chunks(4) {
+				pixels.extend_from_slice(&[px[2], px[1], px[0], px[3]][..bits_per_pixel as usize / 8]);
+			}
+			pixels.resize(start + stride, 0);
+		}
+		let stored_height = if top_down { -(height as i32) } else { height as i32 };
+		let mut out = BMP_SIGNATURE.to_vec();
+		out.extend_from_slice(&(54 + pixels.len() as u32).to_le_bytes());
+		out.extend_from_slice(&[0; 4]);
+		out.extend_from_slice(&54u32.to_le_bytes());
+		out.extend_from_slice(&40u32.to_le_bytes());
+		out.extend_from_slice(&(width as i32).to_le_bytes());
+		out.extend_from_slice(&stored_height.to_le_bytes());
+		out.extend_from_slice(&1u16.to_le_bytes());
+		out.extend_from_slice(&bits_per_pixel.to_le_bytes());
+		out.extend_from_slice(&[0; 24]);
+		out.extend_from_slice(&pixels);
+		out
+	}
+
+	fn gradient(width: u32, height: u32) -> Vec<u8> {
+		(0..width * height).flat_map(|i| [(i % width * 8) as u8, (i / width * 8) as u8, 99, 200]).collect()
+	}
+
+	#[test]
+	fn decodes_bmp_files_either_way_up() {
+		let rgba = gradient(5, 3);
+		let opaque = rgba.chunks(4).flat_map(|px| [px[0], px[1], px[2], 255]).collect::<Vec<_>>();
+		for top_down in [false, true] {
+			assert_eq!(decode_bmp(&bmp_file(5, 3, 32, top_down, &rgba)).unwrap(), (5, 3, rgba.clone()));
+			// 24bpp rows of 15 bytes are padded to 16.
+			assert_eq!(decode_bmp(&bmp_file(5, 3, 24, top_down, &rgba)).unwrap(), (5, 3, opaque.clone()));
+		}
+		// A zeroed alpha byte is the reserved byte of BI_RGB, not transparency.
+		let zeroed = opaque.chunks(4).flat_map(|px| [px[0], px[1], px[2], 0]).collect::<Vec<_>>();
+		assert_eq!(decode_bmp(&bmp_file(5, 3, 32, false, &zeroed)).unwrap().2, opaque);
+		assert!(matches!(decode_bmp(&bmp_file(5, 3, 32, false, &rgba)[..60]), Err(PngIcoError::InvalidBmp(_))));
+		assert!(matches!(decode_bmp(b"BM"), Err(PngIcoError::InvalidBmp(_))));
+	}
+
+	#[test]
+	fn converts_bmp_files_to_icons() {
+		let rgba = gradient(32, 32);
+		let ico = crate::png::encode_ico_from_bytes(&[&bmp_file(32, 32, 32, false, &rgba)], Default::default()).unwrap();
+		let entries = crate::png::parse_ico_bytes(&ico).unwrap();
+		assert_eq!((entries[0].width, entries[0].height), (32, 32));
+		assert_eq!(crate::png::testing::decode_rgba(&entries[0].data).unwrap(), (32, 32, rgba));
+	}
+}
@@ -0,0 +1,152 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::png::InputFormat;
+
+#[derive(Debug)]
+pub enum PngIcoError {
+	#[cfg(feature = "std")]
+	UnreadableFile(io::Error),
+	#[cfg(feature = "std")]
+	UnwritableFile(io::Error),
+	BadSignature(usize, u8),
+	FileTooShort(usize),
+	UnknownChunk(String),
+	CrcMismatch(String),
+	TruncatedChunk(String),
+	ChunkLengthMismatch(String, u32, usize),
+	InvalidChunkLength(String),
+	MalformedChunk(String),
+	MissingHeader,
+	Nonconformant(&'static str),
+	MissingPalette,
+	InvalidPalette,
+	InvalidTransparency,
+	Decompression(&'static str),
+	InvalidImageData(&'static str),
+	InvalidColorType(u8),
+	InvalidBitDepth(u8, u8),
+	InvalidMethod(&'static str, u8),
+	ZeroDimension(u32, u32),
+	DimensionTooLarge(u32),
+	MemoryLimitExceeded(u64, u64),
+	InvalidSize(u32),
+	NotSquare(u32, u32),
+	InvalidColorPlanes(u16),
+	InvalidEntryBitDepth(u8),
+	NoImages,
+	EntryCountMismatch(u16, usize),
+	InvalidIco(&'static str),
+	InvalidIcoAt(usize, &'static str),
+	EntryOutOfBounds(usize, u32, u32, usize),
+	InvalidBmp(&'static str),
+	PalettedBmp(&'static str),
+	InvalidJpeg(&'static str),
+	InvalidGif(&'static str),
+	InvalidTga(&'static str),
+	UnsupportedFormat(InputFormat),
+	TooManyImages(usize),
+	IcoTooLarge,
+	MetadataMismatch,
+}
+
+impl fmt::Display for PngIcoError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			#[cfg(feature = "std")]
+			PngIcoError::UnreadableFile(err) => write!(f, "Could not read file: {}", err),
+			#[cfg(feature = "std")]
+			PngIcoError::UnwritableFile(err) => write!(f, "Could not write ICO file: {}", err),
+			PngIcoError::BadSignature(position, found) => write!(
+				f,
+				"File is not a PNG: signature byte {} is {:#04x}, expected {:#04x}.",
+				position,
+				found,
+				crate::png::PNG_SIGNATURE[*position]
+			),
+			PngIcoError::FileTooShort(len) => write!(
+				f,
+				"File too short to contain a PNG header (need >= {} bytes, got {}).",
+				crate::png::MIN_PNG_SIZE,
+				len
+			),
+			PngIcoError::UnknownChunk(name) => write!(f, "Unknown chunk type {}.", name),
+			PngIcoError::CrcMismatch(name) => write!(f, "CRC mismatch in {} chunk.", name),
+			PngIcoError::InvalidChunkLength(name) => write!(f, "The {} chunk has an invalid length.", name),
+			PngIcoError::MalformedChunk(name) => write!(f, "The {} chunk is malformed.", name),
+			PngIcoError::TruncatedChunk(name) => write!(f, "The {} chunk runs past the end of the file.", name),
+			PngIcoError::ChunkLengthMismatch(name, declared, available) => write!(
+				f,
+				"The {} chunk declares {} bytes of data, but the file only has room for {}.",
+				name, declared, available
+			),
+			PngIcoError::MissingHeader => write!(f, "First chunk was not IHDR."),
+			PngIcoError::Nonconformant(reason) => write!(f, "The PNG does not follow the spec: {}.", reason),
+			PngIcoError::MissingPalette => write!(f, "Indexed PNG has no PLTE chunk."),
+			PngIcoError::InvalidTransparency => write!(f, "tRNS chunk does not match the image color type."),
+			PngIcoError::InvalidPalette => write!(f, "PLTE chunk length is not a multiple of 3 or exceeds 256 entries."),
+			PngIcoError::Decompression(reason) => write!(f, "Could not decompress image data: {}.", reason),
+			PngIcoError::InvalidImageData(reason) => write!(f, "Invalid image data: {}.", reason),
+			PngIcoError::InvalidColorType(color_type) => write!(f, "Invalid color type {}.", color_type),
+			PngIcoError::InvalidBitDepth(bit_depth, color_type) => {
+				write!(f, "Bit depth {} is not allowed for color type {}.", bit_depth, color_type)
+			}
+			PngIcoError::InvalidMethod(field, method) => write!(f, "Invalid {} method {} in IHDR.", field, method),
+			PngIcoError::ZeroDimension(width, height) => write!(f, "PNG has zero width/height ({}x{}).", width, height),
+			PngIcoError::DimensionTooLarge(size) => {
+				let max = crate::png::MAX_ICON_DIMENSION;
+				write!(f, "Image is too large ({}px). Max is {}x{}.", size, max, max)
+			}
+			PngIcoError::MemoryLimitExceeded(size, limit) => {
+				write!(f, "Decoding the image would take {} bytes, more than the limit of {}.", size, limit)
+			}
+			PngIcoError::InvalidSize(size) => write!(f, "Invalid icon size {}.", size),
+			PngIcoError::NotSquare(width, height) => write!(f, "Image is not square ({}x{}).", width, height),
+			PngIcoError::InvalidEntryBitDepth(depth) => {
+				write!(f, "Entries can have 1, 2, 4, 8 or 32 bits per pixel, not {}.", depth)
+			}
+			PngIcoError::InvalidColorPlanes(planes) => write!(f, "Icons have 0 or 1 color planes, not {}.", planes),
+			PngIcoError::InvalidIco(reason) => write!(f, "Invalid ICO file: {}.", reason),
+			PngIcoError::InvalidIcoAt(offset, reason) => write!(f, "Invalid ICO file at byte {}: {}.", offset, reason),
+			PngIcoError::EntryOutOfBounds(index, offset, size, len) => write!(
+				f,
+				"ICO entry {} has {} bytes at offset {}, which runs past the end of the {}-byte file.",
+				index, size, offset, len
+			),
+			PngIcoError::InvalidBmp(reason) => write!(f, "Invalid BMP data: {}.", reason),
+			PngIcoError::PalettedBmp(reason) => write!(f, "Cannot write a paletted BMP entry: {}.", reason),
+			PngIcoError::InvalidJpeg(reason) => write!(f, "Invalid JPEG data: {}.", reason),
+			PngIcoError::InvalidGif(reason) => write!(f, "Invalid GIF data: {}.", reason),
+			PngIcoError::InvalidTga(reason) => write!(f, "Invalid TGA data: {}.", reason),
+			PngIcoError::UnsupportedFormat(InputFormat::Jpeg) => {
+				write!(f, "JPEG images can only be converted with the `jpeg` feature.")
+			}
+			PngIcoError::UnsupportedFormat(InputFormat::Gif) => {
+				write!(f, "GIF images can only be converted with the `gif` feature.")
+			}
+			PngIcoError::UnsupportedFormat(InputFormat::Tga) => {
+				write!(f, "TGA images can only be converted with the `tga` feature.")
+			}
+			PngIcoError::UnsupportedFormat(format) => write!(f, "{} images cannot be converted.", format.name()),
+			PngIcoError::NoImages => write!(f, "No images were provided."),
+			PngIcoError::EntryCountMismatch(count, written) => {
+				write!(f, "The ICO header counts {} images, but the directory lists {}.", count, written)
+			}
+			PngIcoError::TooManyImages(count) => write!(f, "Too many images ({}).", count),
+			PngIcoError::IcoTooLarge => write!(f, "The entries do not fit in the 4 GiB an ICO directory can address."),
+			PngIcoError::MetadataMismatch => write!(f, "The PNG header given does not match the file."),
+		}
+	}
+}
+
+impl core::error::Error for PngIcoError {
+	fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+		match self {
+			#[cfg(feature = "std")]
+			PngIcoError::UnreadableFile(err) | PngIcoError::UnwritableFile(err) => Some(err),
+			_ => None,
+		}
+	}
+}
@@ -0,0 +1,90 @@
+// A C interface to the converter, declared in include/pngtoico.h. Build the crate with the `ffi` feature as a cdylib
+// or staticlib to use it, e.g. `cargo rustc --release --features ffi --crate-type cdylib`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{png, PngIcoError};
+
+/// An argument was null or not valid UTF-8.
+pub const PNGTOICO_ERROR_INVALID_ARGUMENT: c_int = -1;
+/// The input file could not be read.
+pub const PNGTOICO_ERROR_UNREADABLE_INPUT: c_int = -2;
+/// The output file could not be written.
+pub const PNGTOICO_ERROR_UNWRITABLE_OUTPUT: c_int = -3;
+/// The input is not a valid image, or can't be stored in an ICO.
+pub const PNGTOICO_ERROR_INVALID_IMAGE: c_int = -4;
+/// The converter panicked. This is a bug in the library.
+pub const PNGTOICO_ERROR_INTERNAL: c_int = -5;
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: Option<String>) {
+	// Messages come from Display impls and paths, which may contain NULs in theory; those are dropped.
+	let message = message.map(|message| CString::new(message.replace('\0', "")).unwrap_or_default());
+	LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn error_code(err: &PngIcoError) -> c_int {
+	match err {
+		PngIcoError::UnreadableFile(_) => PNGTOICO_ERROR_UNREADABLE_INPUT,
+		PngIcoError::UnwritableFile(_) => PNGTOICO_ERROR_UNWRITABLE_OUTPUT,
+		_ => PNGTOICO_ERROR_INVALID_IMAGE,
+	}
+}
+
+// Borrows a C string argument as a &str.
+//
+// SAFETY: `ptr` must be null or point to a NUL-terminated string that outlives the returned reference.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+	if ptr.is_null() {
+		return Err(format!("{} is null", name));
+	}
+	CStr::from_ptr(ptr).to_str().map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+/// Converts the PNG at `input` into an ICO at `output`, like [`png_to_ico`](crate::png_to_ico). The strings are only
+/// borrowed for the duration of the call.
+/// Returns 0 on success, or one of the negative `PNGTOICO_ERROR_*` codes, with the reason available from
+/// [`pngtoico_last_error`].
+///
+/// # Safety
+///
+/// `input` and `output` must each be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn pngtoico_convert(input: *const c_char, output: *const c_char) -> c_int {
+	let (input, output) = match (str_arg(input, "input"), str_arg(output, "output")) {
+		(Ok(input), Ok(output)) => (input, output),
+		(Err(message), _) | (_, Err(message)) => {
+			set_last_error(Some(message));
+			return PNGTOICO_ERROR_INVALID_ARGUMENT;
+		}
+	};
+	// Unwinding out of an extern "C" function aborts, so panics are reported as an error code instead.
+	match panic::catch_unwind(AssertUnwindSafe(|| png::png_to_ico(input, output))) {
+		Ok(Ok(())) => {
+			set_last_error(None);
+			0
+		}
+		Ok(Err(err)) => {
+			set_last_error(Some(err.to_string()));
+			error_code(&err)
+		}
+		Err(_) => {
+			set_last_error(Some(String::from("internal error")));
+			PNGTOICO_ERROR_INTERNAL
+		}
+	}
+}
+
+/// The message for the last failed call on this thread, or null if the last call succeeded.
+/// The string is owned by the library and must not be freed. It stays valid until the next call into the library on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn pngtoico_last_error() -> *const c_char {
+	LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
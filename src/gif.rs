@@ -0,0 +1,142 @@
+use alloc::{vec, vec::Vec};
+use core::num::NonZeroU64;
+
+use gif::{ColorOutput, DecodeOptions, DecodingError, MemoryLimit};
+
+use crate::png::{check_rgba_size, DEFAULT_MEMORY_LIMIT};
+use crate::{PngIcoError, Result};
+
+fn invalid(error: DecodingError) -> PngIcoError {
+	PngIcoError::InvalidGif(match error {
+		DecodingError::UnexpectedEof | DecodingError::Io(_) => "unexpected end of file",
+		DecodingError::OutOfMemory | DecodingError::MemoryLimit => "frame is too large",
+		DecodingError::LzwError(_) | DecodingError::EndCodeNotFound => "corrupt image data",
+		_ => "malformed file",
+	})
+}
+
+// Decodes the first frame of a GIF into 8-bit RGBA rows, top row first, on a canvas the size of the logical screen.
+// Pixels outside the frame and those with the transparent index are fully transparent. Also returns whether more
+// frames follow, for animated GIFs.
+pub(crate) fn decode_gif(data: &[u8]) -> Result<(u32, u32, Vec<u8>, bool)> {
+	let mut options = DecodeOptions::new();
+	options.set_color_output(ColorOutput::RGBA);
+	options.set_memory_limit(MemoryLimit::Bytes(NonZeroU64::new(DEFAULT_MEMORY_LIMIT).unwrap()));
+	let mut decoder = options.read_info(data).map_err(invalid)?;
+	let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+	if width == 0 || height == 0 {
+		return Err(PngIcoError::InvalidGif("logical screen has no pixels"));
+	}
+	check_rgba_size(width as u32, height as u32)?;
+
+	let frame = decoder.read_next_frame().map_err(invalid)?.ok_or(PngIcoError::InvalidGif("no image"))?;
+	let mut canvas = vec![0; width * height * 4];
+	let (left, top, frame_width) = (frame.left as usize, frame.top as usize, frame.width as usize);
+	for (frame_y, row) in frame.buffer.chunks(frame_width.max(1) * 4).enumerate() {
+		let y = top + frame_y;
+		if y >= height || left >= width {
+			continue;
+		}
+		// Frames may extend past the logical screen, which clips them.
+		let row = &row[..row.len().min((width - left) * 4)];
+		let start = (y * width + left) * 4;
+		for (pixel, color) in canvas[start..start + row.len()].chunks_mut(4).zip(row.chunks(4)) {
+			// The decoder gives transparent pixels their palette color, which is left out so they're all alike.
+			if color[3] != 0 {
+				pixel.copy_from_slice(color);
+			}
+		}
+	}
+	let animated = decoder.next_frame_info().map_err(invalid)?.is_some();
+	Ok((width as u32, height as u32, canvas, animated))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::borrow::Cow;
+
+	use gif::{Encoder, Frame};
+
+	use super::*;
+
+	const PALETTE: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+
+	fn frame(left: u16, top: u16, width: u16, height: u16, indices: &[u8]) -> Frame<'static> {
+		Frame {
+			left,
+			top,
+			width,
+			height,
+			buffer: Cow::Owned(indices.to_vec()),
+			..Frame::default()
+		}
+	}
+
+	fn encode(width: u16, height: u16, frames: &[Frame]) -> Vec<u8> {
+		let mut gif = Vec::new();
+		let mut encoder = Encoder::new(&mut gif, width, height, &PALETTE).unwrap();
+		for frame in frames {
+			encoder.write_frame(frame).unwrap();
+		}
+		drop(encoder);
+		gif
+	}
+
+	fn colors(rgba: &[u8]) -> Vec<[u8; 4]> {
+		rgba.chunks(4).map(|px| [px[0], px[1], px[2], px[3]]).collect()
+	}
+
+	const RED: [u8; 4] = [255, 0, 0, 255];
+	const GREEN: [u8; 4] = [0, 255, 0, 255];
+	const BLUE: [u8; 4] = [0, 0, 255, 255];
+	const CLEAR: [u8; 4] = [0; 4];
+
+	#[test]
+	fn uses_only_the_first_frame_of_animations() {
+		let gif = encode(2, 2, &[frame(0, 0, 2, 2, &[0, 1, 1, 0]), frame(0, 0, 2, 2, &[2, 2, 2, 2])]);
+		let (width, height, rgba, animated) = decode_gif(&gif).unwrap();
+		assert_eq!((width, height, animated), (2, 2, true));
+		assert_eq!(colors(&rgba), [RED, GREEN, GREEN, RED]);
+
+		let (.., animated) = decode_gif(&encode(2, 2, &[frame(0, 0, 2, 2, &[0, 1, 1, 0])])).unwrap();
+		assert!(!animated);
+	}
+
+	#[test]
+	fn places_the_frame_on_a_transparent_screen() {
+		let mut transparent = frame(1, 1, 2, 1, &[3, 2]);
+		transparent.transparent = Some(3);
+		let (.., rgba, _) = decode_gif(&encode(3, 2, &[transparent])).unwrap();
+		assert_eq!(colors(&rgba), [CLEAR, CLEAR, CLEAR, CLEAR, CLEAR, BLUE]);
+	}
+
+	#[test]
+	fn deinterlaces_frames() {
+		// Interlaced rows are stored in four passes: every eighth row from 0, every eighth from 4, every fourth from 2
+		// and every second from 1. With five rows, that's 0, 4, 2, 1 and 3.
+		let mut interlaced = frame(0, 0, 1, 5, &[0, 2, 1, 0, 2]);
+		interlaced.interlaced = true;
+		let (.., rgba, _) = decode_gif(&encode(1, 5, &[interlaced])).unwrap();
+		assert_eq!(colors(&rgba), [RED, RED, GREEN, BLUE, BLUE]);
+	}
+
+	#[test]
+	fn rejects_screens_over_the_memory_limit() {
+		let gif = encode(u16::MAX, u16::MAX, &[frame(0, 0, 1, 1, &[0])]);
+		assert!(matches!(decode_gif(&gif), Err(PngIcoError::MemoryLimitExceeded(..))));
+	}
+
+	#[cfg(feature = "log")]
+	#[test]
+	fn warns_when_converting_animations() {
+		use crate::png::{encode_ico_from_bytes, test_support::captured_warnings, EntryFormat};
+
+		let still = encode(2, 2, &[frame(0, 0, 2, 2, &[0, 1, 1, 0])]);
+		let animated = encode(2, 2, &[frame(0, 0, 2, 2, &[0, 1, 1, 0]), frame(0, 0, 2, 2, &[2, 2, 2, 2])]);
+		let warnings = captured_warnings(|| {
+			encode_ico_from_bytes(&[&still], EntryFormat::Png).unwrap();
+			encode_ico_from_bytes(&[&animated], EntryFormat::Png).unwrap();
+		});
+		assert_eq!(warnings, ["GIF is animated; only its first frame is used"]);
+	}
+}
@@ -0,0 +1,116 @@
+use alloc::vec::Vec;
+
+use jpeg_decoder::{Decoder, Error, PixelFormat};
+
+use crate::png::check_rgba_size;
+use crate::{PngIcoError, Result};
+
+fn invalid(error: Error) -> PngIcoError {
+	PngIcoError::InvalidJpeg(match error {
+		Error::Format(_) => "malformed image data",
+		Error::Unsupported(_) => "the image uses an unsupported JPEG feature",
+		Error::Io(_) => "unexpected end of file",
+		Error::Internal(_) => "the decoder failed",
+	})
+}
+
+// Decodes a baseline or progressive JPEG into opaque 8-bit RGBA rows, top row first. CMYK JPEGs are rejected.
+pub(crate) fn decode_jpeg(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+	let mut decoder = Decoder::new(data);
+	decoder.read_info().map_err(invalid)?;
+	let info = decoder.info().ok_or(PngIcoError::InvalidJpeg("missing frame header"))?;
+	let (width, height) = (info.width as u32, info.height as u32);
+	check_rgba_size(width, height)?;
+	let pixels = decoder.decode().map_err(invalid)?;
+
+	let rgba = match info.pixel_format {
+		PixelFormat::L8 => pixels.iter().flat_map(|&luma| [luma, luma, luma, 255]).collect(),
+		PixelFormat::RGB24 => pixels.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+		// Lossless JPEGs with more than 8 bits per sample, and CMYK.
+		PixelFormat::L16 | PixelFormat::CMYK32 => {
+			return Err(PngIcoError::InvalidJpeg("only 8-bit grayscale and color JPEGs are supported"))
+		}
+	};
+	Ok((width, height, rgba))
+}
+
+#[cfg(test)]
+mod tests {
+	use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+	use super::*;
+	use crate::png::{encode_ico_from_bytes, parse_ico_bytes, EntryFormat, PngParser};
+
+	fn gradient(width: u16, height: u16) -> Vec<u8> {
+		(0..width as u32 * height as u32)
+			.flat_map(|i| [(i % width as u32 * 4) as u8, (i / width as u32 * 4) as u8, 128])
+			.collect()
+	}
+
+	type Setup = fn(&mut Encoder<&mut Vec<u8>>);
+
+	fn encode(pixels: &[u8], width: u16, height: u16, color: ColorType, setup: Setup) -> Vec<u8> {
+		let mut jpeg = Vec::new();
+		let mut encoder = Encoder::new(&mut jpeg, 90);
+		setup(&mut encoder);
+		encoder.encode(pixels, width, height, color).unwrap();
+		jpeg
+	}
+
+	fn assert_close(actual: &[u8], expected: &[u8], tolerance: u8) {
+		assert_eq!(actual.len(), expected.len());
+		for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+			assert!(a.abs_diff(*e) <= tolerance, "sample {}: {} is not within {} of {}", i, a, tolerance, e);
+		}
+	}
+
+	#[test]
+	fn decodes_grayscale() {
+		let luma = (0..16 * 16).map(|i| (i % 16 * 16) as u8).collect::<Vec<_>>();
+		let jpeg = encode(&luma, 16, 16, ColorType::Luma, |_| {});
+		let (width, height, rgba) = decode_jpeg(&jpeg).unwrap();
+		assert_eq!((width, height), (16, 16));
+		assert!(rgba.chunks(4).all(|px| px[0] == px[1] && px[1] == px[2] && px[3] == 255));
+		let decoded = rgba.chunks(4).map(|px| px[0]).collect::<Vec<_>>();
+		assert_close(&decoded, &luma, 8);
+	}
+
+	#[test]
+	fn decodes_subsampled_chroma() {
+		// 4:2:0 with a size that isn't a whole number of 16x16 MCUs.
+		let red = [200, 30, 40].repeat(30 * 20);
+		let subsampled: Setup = |encoder| encoder.set_sampling_factor(SamplingFactor::R_4_2_0);
+		let jpeg = encode(&red, 30, 20, ColorType::Rgb, subsampled);
+		let (width, height, rgba) = decode_jpeg(&jpeg).unwrap();
+		assert_eq!((width, height), (30, 20));
+		let rgb = rgba.chunks(4).flat_map(|px| [px[0], px[1], px[2]]).collect::<Vec<_>>();
+		assert_close(&rgb, &red, 6);
+	}
+
+	#[test]
+	fn restart_intervals_and_progressive_scans_decode_like_baseline() {
+		let pixels = gradient(40, 24);
+		let baseline = decode_jpeg(&encode(&pixels, 40, 24, ColorType::Rgb, |_| {})).unwrap();
+		let restarts = encode(&pixels, 40, 24, ColorType::Rgb, |encoder| encoder.set_restart_interval(1));
+		assert_eq!(decode_jpeg(&restarts).unwrap(), baseline);
+		let progressive = encode(&pixels, 40, 24, ColorType::Rgb, |encoder| encoder.set_progressive(true));
+		assert_eq!(decode_jpeg(&progressive).unwrap(), baseline);
+	}
+
+	#[test]
+	fn rejects_truncated_files() {
+		let jpeg = encode(&gradient(16, 16), 16, 16, ColorType::Rgb, |_| {});
+		assert!(matches!(decode_jpeg(&jpeg[..jpeg.len() / 2]), Err(PngIcoError::InvalidJpeg(_))));
+	}
+
+	#[test]
+	fn converts_to_an_opaque_64px_icon() {
+		let jpeg = encode(&gradient(64, 64), 64, 64, ColorType::Rgb, |_| {});
+		let ico = encode_ico_from_bytes(&[&jpeg], EntryFormat::Png).unwrap();
+		let entries = parse_ico_bytes(&ico).unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!((entries[0].width, entries[0].height), (64, 64));
+		let decoded = PngParser::new().decode_bytes(&entries[0].data).unwrap();
+		assert!(decoded.png_data.chunks(4).all(|px| px[3] == 255));
+	}
+}
@@ -0,0 +1,28 @@
+// Tests always have std, for their scratch files.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod bmp;
+mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "gif")]
+mod gif;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+pub mod png;
+#[cfg(feature = "std")]
+pub mod resize;
+#[cfg(feature = "tga")]
+mod tga;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use error::PngIcoError;
+#[cfg(feature = "std")]
+pub use png::png_to_ico;
+#[cfg(feature = "wasm")]
+pub use wasm::png_bytes_to_ico;
+
+pub type Result<T> = core::result::Result<T, PngIcoError>;
@@ -1,21 +1,147 @@
 use std::path::PathBuf;
-use std::{error::Error, result::Result};
-
-use ico_rs::png::write_ico;
-
-fn main() -> Result<(), Box<dyn Error>> {
-	let image = PathBuf::from(std::env::args().nth(1).ok_or("Image path was invalid.")?);
-	let image_str = image.to_str().ok_or("Could not convert")?;
-	write_ico(
-		format!(
-			"./{}",
-			image
-				.file_name()
-				.ok_or("Could not get image file name.")?
-				.to_str()
-				.ok_or("Could not convert OsStr to str")?
-		),
-		image_str,
-	)?;
-	Ok(())
+use std::process;
+
+use clap::Parser;
+use iconifier::png::{convert_dir, generate_icon_set, write_cur, ConversionBuilder, PngParser};
+use iconifier::png_to_ico;
+
+// The value of `--sizes`.
+#[derive(Debug, Clone, PartialEq)]
+enum Sizes {
+	Auto,
+	List(Vec<u32>),
+}
+
+/// Converts PNG images to Windows icons and cursors.
+#[derive(Debug, Parser)]
+#[command(name = "iconifier", version)]
+struct Args {
+	/// The image to convert, or with --dir the directory of PNGs.
+	input: PathBuf,
+	/// Where to write. Defaults to the input, or the input directory with --dir; the extension is replaced with .ico
+	/// or .cur.
+	output: Option<PathBuf>,
+	/// Builds a multi-size icon from sizes like 16,32,48,256, or with `auto` every standard size up to the image's own.
+	#[arg(long, value_name = "SIZES|auto", value_parser = parse_sizes, conflicts_with = "cursor")]
+	sizes: Option<Sizes>,
+	/// Writes a .cur cursor instead of an icon.
+	#[arg(long)]
+	cursor: bool,
+	/// The cursor's click point in pixels from the top-left corner, like 4,4.
+	#[arg(long, value_name = "X,Y", value_parser = parse_hotspot, requires = "cursor")]
+	hotspot: Option<(u16, u16)>,
+	/// Converts every PNG in the input directory and reports any that failed.
+	#[arg(long, conflicts_with_all = ["sizes", "cursor"])]
+	dir: bool,
+}
+
+fn parse_list<T: std::str::FromStr>(value: &str) -> Result<Vec<T>, String> {
+	value
+		.split(',')
+		.map(|item| item.trim().parse().map_err(|_| format!("{:?} is not a valid number", item)))
+		.collect()
+}
+
+fn parse_sizes(value: &str) -> Result<Sizes, String> {
+	match value {
+		"auto" => Ok(Sizes::Auto),
+		list => parse_list(list).map(Sizes::List),
+	}
+}
+
+fn parse_hotspot(value: &str) -> Result<(u16, u16), String> {
+	match parse_list(value)?[..] {
+		[x, y] => Ok((x, y)),
+		_ => Err(String::from("it takes two values, like 4,4")),
+	}
+}
+
+fn run(args: Args) -> iconifier::Result<()> {
+	let output = args.output.as_ref().unwrap_or(&args.input);
+	if args.dir {
+		let summary = convert_dir(&args.input, output)?;
+		for (path, err) in &summary.failed {
+			eprintln!("error: {}: {}", path.display(), err);
+		}
+		println!(
+			"Converted {} of {} files.",
+			summary.converted.len(),
+			summary.converted.len() + summary.failed.len()
+		);
+		if !summary.is_success() {
+			process::exit(1);
+		}
+		return Ok(());
+	}
+	match &args.sizes {
+		Some(Sizes::List(sizes)) => return generate_icon_set(&args.input, sizes, output),
+		Some(Sizes::Auto) => return ConversionBuilder::new().auto_sizes().convert(&args.input, output),
+		None => {}
+	}
+	if args.cursor {
+		let png = PngParser::new().parse_header(&args.input)?;
+		return write_cur(output, png, &args.input, args.hotspot.unwrap_or((0, 0)));
+	}
+	png_to_ico(&args.input, output)
+}
+
+fn main() {
+	if let Err(err) = run(Args::parse()) {
+		eprintln!("error: {}", err);
+		process::exit(1);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::CommandFactory;
+
+	use super::*;
+
+	fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+		Args::try_parse_from(["iconifier"].iter().chain(args))
+	}
+
+	#[test]
+	fn the_command_is_well_formed() {
+		Args::command().debug_assert();
+	}
+
+	#[test]
+	fn parses_paths() {
+		let args = parse(&["in.png"]).unwrap();
+		assert_eq!((args.input, args.output), (PathBuf::from("in.png"), None));
+		let args = parse(&["in.png", "out.ico"]).unwrap();
+		assert_eq!(args.output, Some(PathBuf::from("out.ico")));
+		assert!(parse(&[]).is_err());
+		assert!(parse(&["a.png", "b.ico", "c.ico"]).is_err());
+	}
+
+	#[test]
+	fn parses_sizes() {
+		assert_eq!(parse(&["in.png", "--sizes", "16, 32,256"]).unwrap().sizes, Some(Sizes::List(vec![16, 32, 256])));
+		assert_eq!(parse(&["in.png", "--sizes", "auto"]).unwrap().sizes, Some(Sizes::Auto));
+		assert!(parse(&["in.png", "--sizes", "16,big"]).is_err());
+		assert!(parse(&["in.png", "--sizes"]).is_err());
+	}
+
+	#[test]
+	fn parses_cursors() {
+		let args = parse(&["in.png", "--cursor", "--hotspot", "4,5"]).unwrap();
+		assert!(args.cursor);
+		assert_eq!(args.hotspot, Some((4, 5)));
+		assert_eq!(parse(&["in.png", "--cursor"]).unwrap().hotspot, None);
+		assert!(parse(&["in.png", "--hotspot", "4,5"]).is_err());
+		assert!(parse(&["in.png", "--cursor", "--hotspot", "4"]).is_err());
+		assert!(parse(&["in.png", "--cursor", "--sizes", "16"]).is_err());
+	}
+
+	#[test]
+	fn parses_directories() {
+		let args = parse(&["--dir", "pngs", "icons"]).unwrap();
+		assert!(args.dir);
+		assert_eq!((args.input, args.output), (PathBuf::from("pngs"), Some(PathBuf::from("icons"))));
+		assert!(parse(&["--dir", "pngs", "--cursor"]).is_err());
+		assert!(parse(&["--dir", "pngs", "--sizes", "auto"]).is_err());
+	}
 }
@@ -0,0 +1,225 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::inflate::zlib_decompress;
+use crate::{PngIcoError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+	/// A single grayscale value that is fully transparent.
+	Gray(u16),
+	/// A single RGB color that is fully transparent.
+	Rgb(u16, u16, u16),
+	/// Alpha for each palette entry in order. Entries past the end are opaque.
+	Palette(Vec<u8>),
+}
+
+impl Transparency {
+	pub(crate) fn parse(data: &[u8], color_type: u8) -> Result<Transparency> {
+		let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+		match (color_type, data.len()) {
+			(0, 2) => Ok(Transparency::Gray(sample(0))),
+			(2, 6) => Ok(Transparency::Rgb(sample(0), sample(2), sample(4))),
+			(3, len) if len <= 256 => Ok(Transparency::Palette(data.to_vec())),
+			_ => Err(PngIcoError::InvalidTransparency),
+		}
+	}
+}
+
+/// The color space of the samples, from the sRGB or gAMA chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+	/// sRGB, with its rendering intent: 0 perceptual, 1 relative colorimetric, 2 saturation, 3 absolute colorimetric.
+	Srgb(u8),
+	/// Only the image gamma is known, times 100000.
+	Gamma(u32),
+}
+
+// Information from ancillary chunks that affects how the decoded pixels should be interpreted.
+#[derive(Debug, Default)]
+pub struct DecodeInfo {
+	pub transparency: Option<Transparency>,
+	pub(crate) physical_dimensions: Option<(u32, u32, bool)>,
+	pub(crate) text: Vec<(String, String)>,
+	pub(crate) frame_count: Option<u32>,
+	pub(crate) color_space: Option<ColorSpace>,
+}
+
+impl DecodeInfo {
+	/// Pixels per unit along the x and y axes from the pHYs chunk.
+	/// The flag is true when the unit is the meter, and false when only the aspect ratio is known.
+	pub fn physical_dimensions(&self) -> Option<(u32, u32, bool)> {
+		self.physical_dimensions
+	}
+
+	/// Keyword and text pairs from every tEXt, zTXt and iTXt chunk, in file order.
+	pub fn text_metadata(&self) -> &[(String, String)] {
+		&self.text
+	}
+
+	/// True when the file is an APNG. ICO can't animate, so only the default image is ever converted.
+	pub fn is_animated(&self) -> bool {
+		self.frame_count.is_some()
+	}
+
+	/// The number of frames declared by the acTL chunk of an APNG.
+	pub fn frame_count(&self) -> Option<u32> {
+		self.frame_count
+	}
+
+	/// The color space from the sRGB chunk, or failing that the gAMA chunk. sRGB takes precedence, as the spec
+	/// requires of decoders that understand it.
+	pub fn color_space(&self) -> Option<ColorSpace> {
+		self.color_space
+	}
+}
+
+pub(crate) fn parse_physical_dimensions(data: &[u8]) -> Result<(u32, u32, bool)> {
+	if data.len() != 9 {
+		return Err(PngIcoError::InvalidChunkLength(String::from("pHYs")));
+	}
+	let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+	let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+	Ok((x, y, data[8] == 1))
+}
+
+// Reads the frame count from an acTL chunk, ignoring the play count.
+pub(crate) fn parse_animation_control(data: &[u8]) -> Result<u32> {
+	if data.len() != 8 {
+		return Err(PngIcoError::InvalidChunkLength(String::from("acTL")));
+	}
+	Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+// Reads the rendering intent from an sRGB chunk.
+pub(crate) fn parse_srgb(data: &[u8]) -> Result<u8> {
+	if data.len() != 1 {
+		return Err(PngIcoError::InvalidChunkLength(String::from("sRGB")));
+	}
+	if data[0] > 3 {
+		return Err(malformed("sRGB"));
+	}
+	Ok(data[0])
+}
+
+pub(crate) fn parse_gamma(data: &[u8]) -> Result<u32> {
+	if data.len() != 4 {
+		return Err(PngIcoError::InvalidChunkLength(String::from("gAMA")));
+	}
+	Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+fn malformed(name: &str) -> PngIcoError {
+	PngIcoError::MalformedChunk(String::from(name))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+	bytes.iter().map(|&byte| byte as char).collect()
+}
+
+// Splits off the bytes up to the next null separator.
+fn split_null<'a>(data: &'a [u8], name: &str) -> Result<(&'a [u8], &'a [u8])> {
+	let end = data.iter().position(|&byte| byte == 0).ok_or_else(|| malformed(name))?;
+	Ok((&data[..end], &data[end + 1..]))
+}
+
+pub(crate) fn parse_text(data: &[u8]) -> Result<(String, String)> {
+	let (keyword, text) = split_null(data, "tEXt")?;
+	Ok((latin1(keyword), latin1(text)))
+}
+
+pub(crate) fn parse_compressed_text(data: &[u8], limit: usize) -> Result<(String, String)> {
+	let (keyword, rest) = split_null(data, "zTXt")?;
+	match rest.split_first() {
+		Some((0, compressed)) => Ok((latin1(keyword), latin1(&zlib_decompress(compressed, limit)?))),
+		_ => Err(malformed("zTXt")),
+	}
+}
+
+pub(crate) fn parse_international_text(data: &[u8], limit: usize) -> Result<(String, String)> {
+	let (keyword, rest) = split_null(data, "iTXt")?;
+	if rest.len() < 2 {
+		return Err(malformed("iTXt"));
+	}
+	let (compressed, method) = (rest[0], rest[1]);
+	let (_language, rest) = split_null(&rest[2..], "iTXt")?;
+	let (_translated_keyword, text) = split_null(rest, "iTXt")?;
+	let text = match (compressed, method) {
+		(0, _) => text.to_vec(),
+		(1, 0) => zlib_decompress(text, limit)?,
+		_ => return Err(malformed("iTXt")),
+	};
+	let text = String::from_utf8(text).map_err(|_| malformed("iTXt"))?;
+	Ok((latin1(keyword), text))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::deflate::zlib_compress;
+	use crate::png::test_support::png_with_chunks;
+	use crate::png::{ColorType, PngParser};
+
+	#[test]
+	fn parses_each_form_of_trns() {
+		assert_eq!(Transparency::parse(&[1, 2], 0).unwrap(), Transparency::Gray(0x0102));
+		assert_eq!(Transparency::parse(&[0, 1, 0, 2, 0, 3], 2).unwrap(), Transparency::Rgb(1, 2, 3));
+		assert_eq!(Transparency::parse(&[0, 128], 3).unwrap(), Transparency::Palette(vec![0, 128]));
+		assert_eq!(Transparency::parse(&[], 3).unwrap(), Transparency::Palette(vec![]));
+		for (data, color_type) in [(&[0; 6][..], 0), (&[0; 2], 2), (&[0; 257], 3), (&[0; 2], 4), (&[0; 2], 6)] {
+			assert!(matches!(Transparency::parse(data, color_type), Err(PngIcoError::InvalidTransparency)));
+		}
+	}
+
+	#[test]
+	fn gray_keys_become_transparent_pixels() {
+		let png = png_with_chunks((2, 1), 8, ColorType::Grayscale, &[(b"tRNS", &[0, 7])], &[7, 8]);
+		let decoded = PngParser::new().decode_bytes(&png).unwrap();
+		assert_eq!(decoded.info.transparency, Some(Transparency::Gray(7)));
+		assert_eq!(decoded.to_rgba8(), [7, 7, 7, 0, 8, 8, 8, 255]);
+	}
+
+	#[test]
+	fn rgb_keys_become_transparent_pixels() {
+		let trns = [0, 1, 0, 2, 0, 3];
+		let png = png_with_chunks((2, 1), 8, ColorType::Truecolor, &[(b"tRNS", &trns)], &[1, 2, 3, 1, 2, 4]);
+		let decoded = PngParser::new().decode_bytes(&png).unwrap();
+		assert_eq!(decoded.info.transparency, Some(Transparency::Rgb(1, 2, 3)));
+		assert_eq!(decoded.to_rgba8(), [1, 2, 3, 0, 1, 2, 4, 255]);
+	}
+
+	#[test]
+	fn palette_alpha_is_reported_per_entry() {
+		let chunks: &[(&[u8; 4], &[u8])] = &[(b"PLTE", &[9; 6]), (b"tRNS", &[64])];
+		let png = png_with_chunks((2, 1), 8, ColorType::Indexed, chunks, &[0, 1]);
+		let info = PngParser::new().decode_info_bytes(&png).unwrap();
+		assert_eq!(info.transparency, Some(Transparency::Palette(vec![64])));
+		assert_eq!(PngParser::new().decode_bytes(&png).unwrap().png_data, [9, 9, 9, 64, 9, 9, 9, 255]);
+	}
+
+	#[test]
+	fn reads_physical_dimensions() {
+		// 3780 pixels per meter is 96 DPI; unit 0 means only the aspect ratio is known.
+		let meters = [&3780u32.to_be_bytes()[..], &3780u32.to_be_bytes(), &[1]].concat();
+		let png = png_with_chunks((1, 1), 8, ColorType::Grayscale, &[(b"pHYs", &meters)], &[0]);
+		let info = PngParser::new().decode_info_bytes(&png).unwrap();
+		assert_eq!(info.physical_dimensions(), Some((3780, 3780, true)));
+		assert_eq!(parse_physical_dimensions(&[0, 0, 0, 2, 0, 0, 0, 1, 0]).unwrap(), (2, 1, false));
+
+		let plain = png_with_chunks((1, 1), 8, ColorType::Grayscale, &[], &[0]);
+		assert_eq!(PngParser::new().decode_info_bytes(&plain).unwrap().physical_dimensions(), None);
+		let short = png_with_chunks((1, 1), 8, ColorType::Grayscale, &[(b"pHYs", &meters[..8])], &[0]);
+		assert!(matches!(PngParser::new().decode_info_bytes(&short), Err(PngIcoError::InvalidChunkLength(_))));
+	}
+
+	#[test]
+	fn compressed_text_inflates_up_to_the_limit() {
+		let ztxt = [&b"Comment\0\0"[..], &zlib_compress(b"hello", 6)].concat();
+		let itxt = [&b"Comment\0\x01\0\0\0"[..], &zlib_compress(b"hello", 6)].concat();
+		let expected = (String::from("Comment"), String::from("hello"));
+		assert_eq!(parse_compressed_text(&ztxt, 5).unwrap(), expected);
+		assert_eq!(parse_international_text(&itxt, 5).unwrap(), expected);
+		assert!(matches!(parse_compressed_text(&ztxt, 4), Err(PngIcoError::Decompression(_))));
+		assert!(matches!(parse_international_text(&itxt, 4), Err(PngIcoError::Decompression(_))));
+	}
+}
@@ -0,0 +1,22 @@
+use std::path::Path;
+
+use super::ico_writer::{checked_png, encode_entries, output_path, ResourceKind};
+use super::{PngMetadata, PngParser};
+use crate::{PngIcoError, Result};
+
+impl PngParser {
+	/// Like [`parse_header`](PngParser::parse_header), but reads the file through `tokio::fs`.
+	pub async fn parse_header_async(&self, path: impl AsRef<Path>) -> Result<PngMetadata> {
+		let data = tokio::fs::read(path.as_ref()).await.map_err(PngIcoError::UnreadableFile)?;
+		self.parse_header_bytes(&data)
+	}
+}
+
+/// Like [`write_ico`](super::write_ico), but reads and writes the files through `tokio::fs`.
+pub async fn write_ico_async(out_path: impl AsRef<Path>, png: PngMetadata, png_path: impl AsRef<Path>) -> Result<()> {
+	let data = tokio::fs::read(png_path.as_ref()).await.map_err(PngIcoError::UnreadableFile)?;
+	let (png, data) = checked_png(&PngParser::new(), &png, data)?;
+	let buf = encode_entries(&[(&png, &data)])?;
+	let out_path = output_path(out_path, &ResourceKind::ICON);
+	tokio::fs::write(out_path, buf).await.map_err(PngIcoError::UnwritableFile)
+}
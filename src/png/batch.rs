@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+use super::png_to_ico;
+use crate::{PngIcoError, Result};
+
+/// The outcome of [`convert_dir`]. A file that fails doesn't stop the others from being converted.
+#[derive(Debug, Default)]
+pub struct DirConversion {
+	/// The ICO files that were written.
+	pub converted: Vec<PathBuf>,
+	/// Each PNG that could not be converted, with the reason.
+	pub failed: Vec<(PathBuf, PngIcoError)>,
+}
+
+impl DirConversion {
+	pub fn is_success(&self) -> bool {
+		self.failed.is_empty()
+	}
+}
+
+// Called with the number of files done, the total and the path of the file just finished.
+type Progress<'a> = dyn FnMut(usize, usize, &Path) + 'a;
+
+fn is_png(path: &Path) -> bool {
+	path.is_file() && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// Converts every .png file directly inside `in_dir` to an ICO with the same stem in `out_dir`, creating it if needed.
+/// Only errors from reading `in_dir` or creating `out_dir` are returned; per-file errors are collected in the result.
+/// With the `parallel` feature the files are converted concurrently; the summary is in file name order either way.
+pub fn convert_dir(in_dir: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> Result<DirConversion> {
+	convert_dir_with_progress(in_dir, out_dir, None)
+}
+
+/// Like [`convert_dir`], but calls `progress` after each file is converted, successfully or not, with the number of
+/// files done so far, the total and the file's path. It is always called on the calling thread, in the order the
+/// files finish, which with the `parallel` feature need not be file name order.
+pub fn convert_dir_with_progress(
+	in_dir: impl AsRef<Path>,
+	out_dir: impl AsRef<Path>,
+	progress: Option<&mut Progress>,
+) -> Result<DirConversion> {
+	let out_dir = out_dir.as_ref();
+	let mut inputs = std::fs::read_dir(in_dir)
+		.map_err(PngIcoError::UnreadableFile)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<std::io::Result<Vec<_>>>()
+		.map_err(PngIcoError::UnreadableFile)?;
+	inputs.retain(|path| is_png(path));
+	inputs.sort();
+	std::fs::create_dir_all(out_dir).map_err(PngIcoError::UnwritableFile)?;
+
+	let outputs = inputs
+		.iter()
+		// file_name is always present for paths returned by read_dir.
+		.map(|input| out_dir.join(input.file_name().unwrap()).with_extension("ico"))
+		.collect::<Vec<_>>();
+	let mut ignore = |_: usize, _: usize, _: &Path| {};
+	let results = convert_all(&inputs, &outputs, progress.unwrap_or(&mut ignore));
+
+	let mut summary = DirConversion::default();
+	for ((input, output), result) in inputs.into_iter().zip(outputs).zip(results) {
+		match result {
+			Ok(()) => summary.converted.push(output),
+			Err(err) => summary.failed.push((input, err)),
+		}
+	}
+	Ok(summary)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn convert_all(inputs: &[PathBuf], outputs: &[PathBuf], progress: &mut Progress) -> Vec<Result<()>> {
+	let results = inputs.iter().zip(outputs).enumerate().map(|(index, (input, output))| {
+		let result = png_to_ico(input, output);
+		progress(index + 1, inputs.len(), input);
+		result
+	});
+	results.collect()
+}
+
+// Converts the files on rayon's thread pool. The pool runs on a scoped thread while this one waits for each finished
+// index over a channel, so `progress` runs on the calling thread and needn't be Send. The collected results are in
+// input order however the work was interleaved.
+#[cfg(feature = "parallel")]
+fn convert_all(inputs: &[PathBuf], outputs: &[PathBuf], progress: &mut Progress) -> Vec<Result<()>> {
+	use rayon::prelude::*;
+	use std::sync::mpsc;
+
+	let (finished, receiver) = mpsc::channel();
+	std::thread::scope(|scope| {
+		let converting = scope.spawn(move || {
+			let results = inputs.par_iter().zip(outputs).enumerate().map(|(index, (input, output))| {
+				let result = png_to_ico(input, output);
+				// The receiver outlives the conversion, so this can't fail.
+				let _ = finished.send(index);
+				result
+			});
+			results.collect::<Vec<_>>()
+		});
+		// The channel closes once the conversion is done and has dropped its sender.
+		for (done, index) in receiver.iter().enumerate() {
+			progress(done + 1, inputs.len(), &inputs[index]);
+		}
+		converting.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::{rgba_png, temp_dir};
+
+	#[test]
+	fn converts_each_png_like_png_to_ico() {
+		let dir = temp_dir("convert-dir");
+		let (in_dir, out_dir, reference) = (dir.join("in"), dir.join("out"), dir.join("reference"));
+		std::fs::create_dir_all(&in_dir).unwrap();
+		std::fs::create_dir_all(&reference).unwrap();
+		for size in [16, 24, 32, 48, 64] {
+			let png = rgba_png(size, size, |x, y| [x as u8 * 4, y as u8 * 4, size as u8, 255]);
+			std::fs::write(in_dir.join(format!("icon{}.png", size)), png).unwrap();
+		}
+		std::fs::write(in_dir.join("broken.png"), b"not a png").unwrap();
+		std::fs::write(in_dir.join("notes.txt"), b"not an image").unwrap();
+
+		let mut calls = Vec::new();
+		let mut progress = |done: usize, total: usize, _: &Path| calls.push((done, total));
+		let summary = convert_dir_with_progress(&in_dir, &out_dir, Some(&mut progress)).unwrap();
+		assert_eq!(calls, (1..=6).map(|done| (done, 6)).collect::<Vec<_>>());
+		assert_eq!(summary.failed.len(), 1);
+		assert_eq!(summary.failed[0].0, in_dir.join("broken.png"));
+		let names = summary.converted.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>();
+		assert_eq!(names, ["icon16.ico", "icon24.ico", "icon32.ico", "icon48.ico", "icon64.ico"]);
+
+		for converted in &summary.converted {
+			let name = converted.file_name().unwrap();
+			png_to_ico(in_dir.join(name).with_extension("png"), reference.join(name)).unwrap();
+			assert_eq!(std::fs::read(converted).unwrap(), std::fs::read(reference.join(name)).unwrap());
+		}
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn collects_each_failure_and_carries_on() {
+		let dir = temp_dir("convert-dir-failures");
+		let (in_dir, out_dir) = (dir.join("in"), dir.join("out"));
+		std::fs::create_dir_all(in_dir.join("folder.png")).unwrap();
+		std::fs::write(in_dir.join("a_short.png"), &rgba_png(8, 8, |_, _| [0; 4])[..20]).unwrap();
+		std::fs::write(in_dir.join("b_huge.png"), rgba_png(300, 300, |_, _| [0; 4])).unwrap();
+		std::fs::write(in_dir.join("c_valid.PNG"), rgba_png(8, 8, |_, _| [9; 4])).unwrap();
+
+		let summary = convert_dir(&in_dir, &out_dir).unwrap();
+		assert!(!summary.is_success());
+		assert_eq!(summary.converted, [out_dir.join("c_valid.ico")]);
+		assert!(out_dir.join("c_valid.ico").is_file());
+		let failed = summary.failed.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>();
+		assert_eq!(failed, [in_dir.join("a_short.png"), in_dir.join("b_huge.png")]);
+		assert!(matches!(summary.failed[0].1, PngIcoError::FileTooShort(20)));
+		assert!(matches!(summary.failed[1].1, PngIcoError::DimensionTooLarge(300)));
+
+		let missing = convert_dir(dir.join("missing"), &out_dir);
+		assert!(matches!(missing, Err(PngIcoError::UnreadableFile(_))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reports_progress_once_per_file() {
+		let dir = temp_dir("convert-dir-progress");
+		let (in_dir, out_dir) = (dir.join("in"), dir.join("out"));
+		std::fs::create_dir_all(&in_dir).unwrap();
+		let inputs = ["a.png", "b.png", "c.png"].map(|name| in_dir.join(name));
+		for input in &inputs {
+			std::fs::write(input, rgba_png(4, 4, |_, _| [1, 2, 3, 255])).unwrap();
+		}
+
+		let mut calls = Vec::new();
+		let mut progress = |done: usize, total: usize, path: &Path| calls.push((done, total, path.to_path_buf()));
+		convert_dir_with_progress(&in_dir, &out_dir, Some(&mut progress)).unwrap();
+		let counts = calls.iter().map(|(done, total, _)| (*done, *total)).collect::<Vec<_>>();
+		assert_eq!(counts, [(1, 3), (2, 3), (3, 3)]);
+		let mut paths = calls.into_iter().map(|(_, _, path)| path).collect::<Vec<_>>();
+		paths.sort();
+		assert_eq!(paths, inputs);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
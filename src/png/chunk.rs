@@ -0,0 +1,220 @@
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::crc::crc32;
+use crate::{PngIcoError, Result};
+
+pub struct RawChunk<'a> {
+	pub name: [u8; 4],
+	/// The length stored before the chunk name. The iterator only returns chunks whose data fits in the file, so it
+	/// always equals `data.len()`.
+	pub declared_len: u32,
+	pub data: &'a [u8],
+	/// The CRC-32 stored after the chunk data, as read from the file. The iterator doesn't check it.
+	pub crc: u32,
+}
+
+impl RawChunk<'_> {
+	/// The CRC-32 of the chunk's name and data, which a valid chunk stores as `crc`.
+	pub fn computed_crc(&self) -> u32 {
+		crc32(&[&self.name, self.data])
+	}
+
+	pub fn crc_matches(&self) -> bool {
+		self.computed_crc() == self.crc
+	}
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+	let mut buf = [0; 4];
+	buf.copy_from_slice(&bytes[..4]);
+	u32::from_be_bytes(buf)
+}
+
+// Lazily walks the chunk stream that follows the 8-byte PNG signature, stopping after IEND.
+pub struct ChunkIter<'a> {
+	data: &'a [u8],
+	pos: usize,
+	trailing: usize,
+}
+
+impl<'a> ChunkIter<'a> {
+	pub(crate) fn new(data: &'a [u8]) -> Self {
+		ChunkIter { data, pos: 0, trailing: 0 }
+	}
+
+	/// The number of bytes after IEND, which the iterator skips. Only known once IEND has been returned.
+	pub fn trailing_len(&self) -> usize {
+		self.trailing
+	}
+}
+
+impl<'a> Iterator for ChunkIter<'a> {
+	type Item = Result<RawChunk<'a>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let rest = &self.data[self.pos..];
+		if rest.is_empty() {
+			return None;
+		}
+		// Any failure means the rest of the stream can't be found, so stop after reporting it.
+		self.pos = self.data.len();
+		if rest.len() < 12 {
+			return Some(Err(PngIcoError::TruncatedChunk(String::from("unknown"))));
+		}
+
+		let declared_len = read_u32(rest);
+		let len = declared_len as usize;
+		let mut name = [0; 4];
+		name.copy_from_slice(&rest[4..8]);
+		if rest.len() - 12 < len {
+			return Some(Err(PngIcoError::ChunkLengthMismatch(
+				String::from_utf8_lossy(&name).into_owned(),
+				declared_len,
+				rest.len() - 12,
+			)));
+		}
+
+		self.pos = if &name == b"IEND" {
+			self.trailing = rest.len() - len - 12;
+			self.data.len()
+		} else {
+			self.data.len() - rest.len() + len + 12
+		};
+		Some(Ok(RawChunk {
+			name,
+			declared_len,
+			data: &rest[8..8 + len],
+			crc: read_u32(&rest[8 + len..]),
+		}))
+	}
+}
+
+// The length of the PNG at the start of `data`, up to the end of its IEND chunk. Anything after IEND, such as data
+// appended to make a polyglot file, isn't part of the image. Without a complete IEND, all of `data` counts.
+pub(crate) fn png_len(data: &[u8]) -> usize {
+	let mut chunks = ChunkIter::new(data.get(8..).unwrap_or_default());
+	while let Some(Ok(chunk)) = chunks.next() {
+		if &chunk.name == b"IEND" {
+			return data.len() - chunks.trailing_len();
+		}
+	}
+	data.len()
+}
+
+// Like png_len, but for the `file_len` bytes of a seekable stream, skipping over each chunk's data instead of holding
+// it in memory.
+#[cfg(feature = "std")]
+pub(crate) fn png_len_from_reader(mut reader: impl Read + Seek, file_len: u64) -> io::Result<u64> {
+	let mut pos = 8;
+	let mut header = [0; 8];
+	while pos + 12 <= file_len {
+		reader.seek(SeekFrom::Start(pos))?;
+		reader.read_exact(&mut header)?;
+		let end = pos + 12 + read_u32(&header) as u64;
+		if end > file_len {
+			break;
+		}
+		pos = end;
+		if &header[4..] == b"IEND" {
+			return Ok(pos);
+		}
+	}
+	Ok(file_len)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::{rgba_png, write_chunk};
+	use crate::png::PngParser;
+
+	fn names(data: &[u8]) -> Vec<[u8; 4]> {
+		ChunkIter::new(&data[8..]).map(|chunk| chunk.unwrap().name).collect()
+	}
+
+	#[test]
+	fn skips_stray_letters_after_iend() {
+		let png = rgba_png(4, 4, |x, y| [x as u8, y as u8, 0, 255]);
+		for trailing in [&b"I"[..], b"xI", b"IE", b"IEN", b"IEND", b"\0\0\0\0IEND"] {
+			let padded = [&png[..], trailing].concat();
+			assert_eq!(names(&padded).last(), Some(b"IEND"));
+			let mut chunks = ChunkIter::new(&padded[8..]);
+			chunks.by_ref().for_each(drop);
+			assert_eq!(chunks.trailing_len(), trailing.len());
+			assert_eq!(png_len(&padded), png.len());
+			PngParser::new().decode_bytes(&padded).unwrap();
+		}
+	}
+
+	#[test]
+	fn reads_chunks_whose_data_ends_in_a_stray_letter() {
+		let png = rgba_png(2, 2, |_, _| [0, 0, 0, 255]);
+		let iend = png.len() - 12;
+		let mut text = png[..iend].to_vec();
+		write_chunk(&mut text, b"tEXt", b"Comment\0I");
+		text.extend_from_slice(&png[iend..]);
+		assert_eq!(names(&text)[names(&text).len() - 2..], [*b"tEXt", *b"IEND"]);
+		assert_eq!(png_len(&text), text.len());
+	}
+
+	#[test]
+	fn reports_streams_cut_off_at_a_stray_letter() {
+		let png = rgba_png(2, 2, |_, _| [0, 0, 0, 255]);
+		// The IEND chunk cut off after its "I", after "IEN", and one byte short of its CRC.
+		for cut in [5, 7, 11] {
+			let truncated = [&png[..png.len() - 12], &b"\0\0\0\0IEND\0\0\0"[..cut]].concat();
+			let last = ChunkIter::new(&truncated[8..]).last().unwrap();
+			assert!(matches!(last, Err(PngIcoError::TruncatedChunk(_))));
+			assert_eq!(png_len(&truncated), truncated.len());
+		}
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	fn measures_streams_as_slices() {
+		let png = rgba_png(2, 2, |_, _| [0, 0, 0, 255]);
+		let polyglot = [&png[..], b"PK\x03\x04"].concat();
+		let truncated = &png[..png.len() - 5];
+		for data in [&png[..], &polyglot, truncated, &png[..8]] {
+			let len = png_len_from_reader(std::io::Cursor::new(data), data.len() as u64).unwrap();
+			assert_eq!(len, png_len(data) as u64);
+		}
+	}
+
+	#[test]
+	fn exposes_stored_and_computed_crcs() {
+		let png = rgba_png(3, 3, |x, y| [x as u8, y as u8, 0, 255]);
+		for chunk in ChunkIter::new(&png[8..]) {
+			let chunk = chunk.unwrap();
+			assert_eq!(chunk.crc, chunk.computed_crc());
+			assert!(chunk.crc_matches());
+		}
+		// Flips a byte of the IHDR data, after the signature and the chunk's length and name.
+		let mut flipped = png.clone();
+		flipped[16] ^= 1;
+		let ihdr = ChunkIter::new(&flipped[8..]).next().unwrap().unwrap();
+		assert_ne!(ihdr.crc, ihdr.computed_crc());
+		assert!(!ihdr.crc_matches());
+	}
+
+	#[test]
+	fn reports_lengths_that_overstate_the_data() {
+		let png = rgba_png(2, 2, |_, _| [0, 0, 0, 255]);
+		for chunk in ChunkIter::new(&png[8..]) {
+			let chunk = chunk.unwrap();
+			assert_eq!(chunk.declared_len as usize, chunk.data.len());
+		}
+		// An IEND claiming 5 bytes of data when only its CRC follows.
+		let mut overstated = png.clone();
+		let iend = overstated.len() - 12;
+		overstated[iend..iend + 4].copy_from_slice(&5u32.to_be_bytes());
+		match ChunkIter::new(&overstated[8..]).last().unwrap() {
+			Err(PngIcoError::ChunkLengthMismatch(name, declared, available)) => {
+				assert_eq!((&name[..], declared, available), ("IEND", 5, 0));
+			}
+			other => panic!("expected ChunkLengthMismatch, got {:?}", other.map(|chunk| chunk.name)),
+		}
+	}
+}
@@ -0,0 +1,68 @@
+use crate::{PngIcoError, Result};
+
+/// The color types allowed in an IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorType {
+	Grayscale,
+	Truecolor,
+	Indexed,
+	GrayscaleAlpha,
+	TruecolorAlpha,
+}
+
+impl ColorType {
+	pub fn from_u8(color_type: u8) -> Result<ColorType> {
+		match color_type {
+			0 => Ok(ColorType::Grayscale),
+			2 => Ok(ColorType::Truecolor),
+			3 => Ok(ColorType::Indexed),
+			4 => Ok(ColorType::GrayscaleAlpha),
+			6 => Ok(ColorType::TruecolorAlpha),
+			color_type => Err(PngIcoError::InvalidColorType(color_type)),
+		}
+	}
+
+	pub fn to_u8(self) -> u8 {
+		match self {
+			ColorType::Grayscale => 0,
+			ColorType::Truecolor => 2,
+			ColorType::Indexed => 3,
+			ColorType::GrayscaleAlpha => 4,
+			ColorType::TruecolorAlpha => 6,
+		}
+	}
+
+	/// The number of samples stored per pixel. Indexed pixels are a single palette index.
+	pub fn channel_count(self) -> u32 {
+		match self {
+			ColorType::Grayscale | ColorType::Indexed => 1,
+			ColorType::GrayscaleAlpha => 2,
+			ColorType::Truecolor => 3,
+			ColorType::TruecolorAlpha => 4,
+		}
+	}
+
+	/// True when every pixel carries an alpha sample. Other color types can still be transparent through tRNS.
+	pub fn has_alpha(self) -> bool {
+		matches!(self, ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha)
+	}
+
+	// The bit depths the PNG spec allows for this color type (section 11.2.2).
+	pub(crate) fn allowed_bit_depths(self) -> &'static [u8] {
+		match self {
+			ColorType::Grayscale => &[1, 2, 4, 8, 16],
+			ColorType::Indexed => &[1, 2, 4, 8],
+			_ => &[8, 16],
+		}
+	}
+
+	pub(crate) fn name(self) -> &'static str {
+		match self {
+			ColorType::Grayscale => "grayscale",
+			ColorType::Truecolor => "truecolor",
+			ColorType::Indexed => "indexed",
+			ColorType::GrayscaleAlpha => "grayscale+alpha",
+			ColorType::TruecolorAlpha => "truecolor+alpha",
+		}
+	}
+}
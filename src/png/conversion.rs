@@ -0,0 +1,559 @@
+use std::path::Path;
+
+use super::encoder::{encode_indexed_png, encode_png, DEFAULT_COMPRESSION_LEVEL};
+use super::ico_writer::{
+	embeddable_png, encode_dimension, encode_payload, encode_resource, reencode_png, write_file, write_output,
+	ResourceKind,
+};
+use super::icon_set::{center_on_canvas, fit_to_square, fit_within};
+use super::input::read_input;
+use super::quantize::median_cut;
+use super::{
+	rgba_metadata, suggested_sizes, BmpOptions, EntryFormat, NonSquarePolicy, PngMetadata, PngParser, MAX_ICON_DIMENSION,
+};
+use crate::resize::{resize_with, ResizeFilter};
+use crate::{PngIcoError, Result};
+
+// A PNG with its header, and the format to store it in.
+type Entry = ((PngMetadata, Vec<u8>), EntryFormat);
+
+// The sizes of the BMP entries added by `bmp_fallback`, which the oldest shells look for.
+const FALLBACK_SIZES: [u32; 2] = [16, 32];
+
+/// An entry of the ICO that [`ConversionBuilder::validate`] found [`convert`](ConversionBuilder::convert) would write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlannedEntry {
+	pub width: u32,
+	pub height: u32,
+	pub format: EntryFormat,
+	/// The size in bytes of the entry's image data.
+	pub len: usize,
+}
+
+/// Collects the options for converting a PNG into an ICO. The defaults match [`png_to_ico`](super::png_to_ico).
+#[derive(Debug, Clone)]
+pub struct ConversionBuilder {
+	auto_scale: bool,
+	format: EntryFormat,
+	// Sizes without a format of their own use `format`.
+	sizes: Option<Vec<(u32, Option<EntryFormat>)>>,
+	auto_sizes: bool,
+	bit_depths: Vec<(u32, u8)>,
+	embed_source: bool,
+	non_square: NonSquarePolicy,
+	filter: ResizeFilter,
+	linear_light: bool,
+	validate_crc: bool,
+	compression_level: Option<u8>,
+	color_planes: u16,
+	exact_output_path: bool,
+	bmp_fallback: bool,
+	transparent_color: Option<[u8; 3]>,
+}
+
+impl Default for ConversionBuilder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ConversionBuilder {
+	pub fn new() -> Self {
+		ConversionBuilder {
+			auto_scale: false,
+			format: EntryFormat::default(),
+			sizes: None,
+			auto_sizes: false,
+			bit_depths: Vec::new(),
+			embed_source: false,
+			non_square: NonSquarePolicy::default(),
+			filter: ResizeFilter::default(),
+			linear_light: true,
+			validate_crc: true,
+			compression_level: None,
+			color_planes: 1,
+			exact_output_path: false,
+			bmp_fallback: false,
+			transparent_color: None,
+		}
+	}
+
+	/// Downscales images larger than 256x256 to fit, keeping their aspect ratio, instead of rejecting them.
+	/// Disabled by default.
+	pub fn auto_scale(mut self, auto_scale: bool) -> Self {
+		self.auto_scale = auto_scale;
+		self
+	}
+
+	/// How each entry is stored. Defaults to [`EntryFormat::Png`].
+	pub fn format(mut self, format: EntryFormat) -> Self {
+		self.format = format;
+		self
+	}
+
+	/// Builds one square entry per size instead of embedding the source at its own size.
+	/// Each size must be between 1 and 256.
+	pub fn sizes(mut self, sizes: &[u32]) -> Self {
+		self.sizes = Some(sizes.iter().map(|&size| (size, None)).collect());
+		self.auto_sizes = false;
+		self
+	}
+
+	/// Builds one entry for each of the source's [`suggested_sizes`], so it is never upscaled. Sources smaller than
+	/// 16 pixels are embedded at their own size. Replaces any earlier `sizes`.
+	pub fn auto_sizes(mut self) -> Self {
+		self.sizes = None;
+		self.auto_sizes = true;
+		self
+	}
+
+	/// Like [`sizes`](Self::sizes), but with a format for each size, so one file can hold e.g. a 256px PNG entry
+	/// alongside small BMP entries for older consumers. Replaces any earlier `sizes`.
+	pub fn size_formats(mut self, entries: &[(u32, EntryFormat)]) -> Self {
+		self.sizes = Some(entries.iter().map(|&(size, format)| (size, Some(format))).collect());
+		self.auto_sizes = false;
+		self
+	}
+
+	/// Keeps the entry for the source at its own size, embedded as it is, in front of the entries built for
+	/// [`sizes`](Self::sizes) and the like. This gives the best fidelity at the native size while still covering the
+	/// smaller ones. A listed size equal to the source's own adds a second, resized entry. Disabled by default.
+	pub fn embed_source(mut self, embed: bool) -> Self {
+		self.embed_source = embed;
+		self
+	}
+
+	/// The bits per pixel of the entries of each size, for e.g. an 8bpp 16px entry beside a 32bpp 256px one. 32 keeps
+	/// them RGBA, while 1, 2, 4 and 8 reduce them to a palette of at most 2^bits colors with median cut and store
+	/// them as indexed PNGs, which [`EntryFormat::Bmp4`] and [`EntryFormat::Bmp8`] can also hold. Sizes that aren't
+	/// listed are 32bpp. Only applies to resized entries, such as those from [`sizes`](Self::sizes).
+	pub fn bit_depths(mut self, depths: &[(u32, u8)]) -> Self {
+		self.bit_depths = depths.to_vec();
+		self
+	}
+
+	/// What to do when the source isn't square. Defaults to [`NonSquarePolicy::Warn`].
+	pub fn non_square(mut self, policy: NonSquarePolicy) -> Self {
+		self.non_square = policy;
+		self
+	}
+
+	/// The filter used whenever the image is resized. Defaults to [`ResizeFilter::Lanczos3`].
+	pub fn filter(mut self, filter: ResizeFilter) -> Self {
+		self.filter = filter;
+		self
+	}
+
+	/// Whether images are resized in linear light, as [`resize`](crate::resize::resize) does, which keeps downscaled
+	/// detail from darkening. Enabled by default; disabling it filters the sRGB values directly, which is faster.
+	pub fn linear_light(mut self, linear: bool) -> Self {
+		self.linear_light = linear;
+		self
+	}
+
+	/// Toggles CRC-32 validation of the source PNG's chunks. Enabled by default.
+	pub fn validate_crc(mut self, validate: bool) -> Self {
+		self.validate_crc = validate;
+		self
+	}
+
+	/// Re-encodes the source PNG at `level`, from 0 (stored) to 9 (smallest), instead of embedding its bytes as they
+	/// are. This shrinks poorly compressed sources, at the cost of decoding them. Resized entries are encoded at
+	/// `level` too. Off by default, so the source's exact bytes end up in the ICO.
+	pub fn compression_level(mut self, level: u8) -> Self {
+		self.compression_level = Some(level.min(9));
+		self
+	}
+
+	/// The color planes field of every directory entry, which must be 0 or 1 for icons. Defaults to 1, which is what
+	/// nearly all tools write; some older ones expect 0.
+	pub fn color_planes(mut self, planes: u16) -> Self {
+		self.color_planes = planes;
+		self
+	}
+
+	/// Writes the ICO to exactly the `output` path given to [`convert`](Self::convert), for build systems that need
+	/// a particular file name. By default its extension is replaced with .ico.
+	pub fn exact_output_path(mut self, exact: bool) -> Self {
+		self.exact_output_path = exact;
+		self
+	}
+
+	/// Also adds 16x16 and 32x32 BMP entries downscaled from the source, whatever the other entries are, for shells so
+	/// old that they only read BMP entries. Sizes that already have a BMP entry aren't added again. Disabled by
+	/// default.
+	pub fn bmp_fallback(mut self, fallback: bool) -> Self {
+		self.bmp_fallback = fallback;
+		self
+	}
+
+	/// Makes every pixel of the source with color `rgb` fully transparent, for sources without alpha such as JPEGs
+	/// and sprite art keyed on e.g. magenta. Those pixels become transparent black, so BMP entries mask them too. The
+	/// source is re-encoded as an RGBA PNG rather than embedded as it is. Off by default.
+	pub fn transparent_color(mut self, rgb: [u8; 3]) -> Self {
+		self.transparent_color = Some(rgb);
+		self
+	}
+
+	/// Converts the PNG at `input` with these options and writes the ICO to `output`.
+	/// `input` may also be a BMP, a JPEG with the `jpeg` feature or a GIF with the `gif` feature; the format is
+	/// recognized by contents, not name.
+	pub fn convert(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+		let (_, buf) = self.encode(input.as_ref())?;
+		if self.exact_output_path {
+			write_file(output, &buf)
+		} else {
+			write_output(output, &buf)
+		}
+	}
+
+	/// Does everything [`convert`](Self::convert) does except write the ICO, for checking an input and options
+	/// without touching the disk. Returns the entries the file would hold, in order, or the error converting would
+	/// fail with.
+	pub fn validate(&self, input: impl AsRef<Path>) -> Result<Vec<PlannedEntry>> {
+		Ok(self.encode(input.as_ref())?.0)
+	}
+
+	// Builds the ICO for `input`, along with the entries in it.
+	fn encode(&self, input: &Path) -> Result<(Vec<PlannedEntry>, Vec<u8>)> {
+		let parser = PngParser::new().validate_crc(self.validate_crc);
+		let mut data = read_input(input)?;
+		if let Some(key) = self.transparent_color {
+			data = self.apply_color_key(&parser, &data, key)?;
+		}
+		let metadata = parser.parse_header_bytes(&data)?;
+		let (width, height) = (metadata.width, metadata.height);
+
+		let mut pad = false;
+		if width != height {
+			match self.non_square {
+				NonSquarePolicy::Error => return Err(PngIcoError::NotSquare(width, height)),
+				#[cfg(feature = "log")]
+				NonSquarePolicy::Warn => log::warn!("{} is not square ({}x{})", input.display(), width, height),
+				#[cfg(not(feature = "log"))]
+				NonSquarePolicy::Warn => {}
+				NonSquarePolicy::PadToSquare => pad = true,
+			}
+		}
+
+		let suggested = if self.auto_sizes {
+			suggested_sizes(width, height).into_iter().map(|size| (size, None)).collect()
+		} else {
+			Vec::new()
+		};
+		let sizes = match &self.sizes {
+			None if !suggested.is_empty() => Some(&suggested),
+			sizes => sizes.as_ref(),
+		};
+		// Built before `data` may be moved into an entry below.
+		let fallback = if self.bmp_fallback {
+			let format = EntryFormat::Bmp(BmpOptions::default());
+			self.sized_entries(&parser, &data, &FALLBACK_SIZES.map(|size| (size, Some(format))))?
+		} else {
+			Vec::new()
+		};
+		let mut entries = match sizes {
+			Some(sizes) => self.sized_entries(&parser, &data, sizes)?,
+			None => Vec::new(),
+		};
+		if sizes.is_none() || self.embed_source {
+			let source = if !pad && width <= MAX_ICON_DIMENSION && height <= MAX_ICON_DIMENSION {
+				match self.compression_level {
+					Some(level) => reencode_png(&parser, &data, level)?,
+					None => embeddable_png(&parser, data)?,
+				}
+			} else {
+				self.reencoded_entry(&parser, &data, pad)?
+			};
+			entries.insert(0, (source, self.format));
+		}
+		// Only the fallback sizes without a BMP entry already are kept.
+		for fallback in fallback {
+			let ((png, _), _) = &fallback;
+			if !entries.iter().any(|((other, _), format)| {
+				other.width == png.width && other.height == png.height && !matches!(format, EntryFormat::Png)
+			}) {
+				entries.push(fallback);
+			}
+		}
+		let mut planned = Vec::with_capacity(entries.len());
+		let mut payloads = Vec::with_capacity(entries.len());
+		for ((png, data), format) in entries {
+			let (png, data) = encode_payload(&parser, png, data, format)?;
+			planned.push(PlannedEntry {
+				width: png.width,
+				height: png.height,
+				format,
+				len: data.len(),
+			});
+			payloads.push((png, data));
+		}
+		let entries = payloads.iter().map(|(png, data)| (png, &data[..])).collect::<Vec<_>>();
+		let kind = ResourceKind::Icon {
+			color_planes: self.color_planes,
+		};
+		Ok((planned, encode_resource(&kind, &entries)?))
+	}
+
+	fn sized_entries(
+		&self,
+		parser: &PngParser,
+		data: &[u8],
+		sizes: &[(u32, Option<EntryFormat>)],
+	) -> Result<Vec<Entry>> {
+		let source = parser.decode_bytes(data)?;
+		let (width, height) = (source.metadata.width, source.metadata.height);
+		let rgba = source.to_rgba8();
+		sizes
+			.iter()
+			.map(|&(size, format)| {
+				// Checked up front so an invalid size fails before any resizing.
+				encode_dimension(size)?;
+				let bit_depth = self.bit_depth(size)?;
+				let icon = fit_to_square(&rgba, width, height, size, self.filter, self.linear_light)?;
+				let color_space = source.info.color_space;
+				let png = match bit_depth {
+					Some(depth @ (1 | 2 | 4 | 8)) => {
+						let (palette, indices) = median_cut(&icon, 1 << depth);
+						let png = encode_indexed_png(&indices, size, size, &palette, depth, color_space, self.level());
+						(parser.parse_header_bytes(&png)?, png)
+					}
+					_ => (rgba_metadata(size, size), encode_png(&icon, size, size, color_space, self.level())),
+				};
+				Ok((png, format.unwrap_or(self.format)))
+			})
+			.collect()
+	}
+
+	// Decodes the source, pads it to a square if asked to, and downscales it if it's still too large.
+	fn reencoded_entry(&self, parser: &PngParser, data: &[u8], pad: bool) -> Result<(PngMetadata, Vec<u8>)> {
+		let source = parser.decode_bytes(data)?;
+		let (mut width, mut height) = (source.metadata.width, source.metadata.height);
+		let mut rgba = source.to_rgba8();
+		if pad {
+			let size = width.max(height);
+			rgba = center_on_canvas(rgba, width, height, size);
+			width = size;
+			height = size;
+		}
+		if width > MAX_ICON_DIMENSION || height > MAX_ICON_DIMENSION {
+			if !self.auto_scale {
+				return Err(PngIcoError::DimensionTooLarge(width.max(height)));
+			}
+			let (fit_w, fit_h) = fit_within(width, height, MAX_ICON_DIMENSION);
+			rgba = resize_with(&rgba, width, height, fit_w, fit_h, self.filter, self.linear_light)?;
+			width = fit_w;
+			height = fit_h;
+		}
+		let png = encode_png(&rgba, width, height, source.info.color_space, self.level());
+		Ok((rgba_metadata(width, height), png))
+	}
+
+	// Decodes the source and re-encodes it with the pixels of color `key` made transparent.
+	fn apply_color_key(&self, parser: &PngParser, data: &[u8], key: [u8; 3]) -> Result<Vec<u8>> {
+		let source = parser.decode_bytes(data)?;
+		let mut rgba = source.to_rgba8();
+		for pixel in rgba.chunks_mut(4) {
+			if pixel[..3] == key {
+				pixel.copy_from_slice(&[0; 4]);
+			}
+		}
+		let (width, height) = (source.metadata.width, source.metadata.height);
+		Ok(encode_png(&rgba, width, height, source.info.color_space, self.level()))
+	}
+
+	// The bits per pixel listed for `size` in `bit_depths`, if any.
+	fn bit_depth(&self, size: u32) -> Result<Option<u8>> {
+		match self.bit_depths.iter().find(|&&(depth_size, _)| depth_size == size) {
+			None => Ok(None),
+			Some(&(_, depth @ (1 | 2 | 4 | 8 | 32))) => Ok(Some(depth)),
+			Some(&(_, depth)) => Err(PngIcoError::InvalidEntryBitDepth(depth)),
+		}
+	}
+
+	fn level(&self) -> u8 {
+		self.compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+	#[cfg(feature = "log")]
+	use crate::png::test_support::captured_warnings;
+	use crate::png::test_support::{rgba_png, temp_dir};
+	use crate::png::{parse_ico, BmpOptions, IcoEntry, PNG_SIGNATURE};
+
+	// A 200x100 opaque red source in `dir`.
+	fn wide_source(dir: &Path) -> PathBuf {
+		let path = dir.join("wide.png");
+		std::fs::write(&path, rgba_png(200, 100, |_, _| [255, 0, 0, 255])).unwrap();
+		path
+	}
+
+	fn rgba(entry: &IcoEntry) -> Vec<u8> {
+		PngParser::new().decode_bytes(&entry.to_png().unwrap()).unwrap().to_rgba8()
+	}
+
+	#[test]
+	fn error_policy_rejects_non_square_sources() {
+		let dir = temp_dir("non-square-error");
+		let builder = ConversionBuilder::new().non_square(NonSquarePolicy::Error);
+		let result = builder.convert(wide_source(&dir), dir.join("out.ico"));
+		assert!(matches!(result, Err(PngIcoError::NotSquare(200, 100))));
+		assert!(!dir.join("out.ico").exists());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn warn_policy_embeds_non_square_sources() {
+		let dir = temp_dir("non-square-warn");
+		let (input, output) = (wide_source(&dir), dir.join("out.ico"));
+		let convert = || ConversionBuilder::new().non_square(NonSquarePolicy::Warn).convert(&input, &output).unwrap();
+		#[cfg(feature = "log")]
+		{
+			let warnings = captured_warnings(convert);
+			assert_eq!(warnings.len(), 1);
+			assert!(warnings[0].contains("200x100"), "{}", warnings[0]);
+		}
+		#[cfg(not(feature = "log"))]
+		convert();
+		let entries = parse_ico(&output).unwrap();
+		assert_eq!((entries[0].width, entries[0].height), (200, 100));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn warn_is_the_default_policy() {
+		assert_eq!(ConversionBuilder::new().non_square, NonSquarePolicy::default());
+		assert_eq!(NonSquarePolicy::default(), NonSquarePolicy::Warn);
+	}
+
+	#[test]
+	fn pad_policy_centers_on_a_transparent_square() {
+		let dir = temp_dir("non-square-pad");
+		let output = dir.join("out.ico");
+		let builder = ConversionBuilder::new().non_square(NonSquarePolicy::PadToSquare);
+		builder.convert(wide_source(&dir), &output).unwrap();
+		let entries = parse_ico(&output).unwrap();
+		assert_eq!((entries[0].width, entries[0].height), (200, 200));
+		let pixels = rgba(&entries[0]);
+		let pixel = |x: usize, y: usize| &pixels[(y * 200 + x) * 4..][..4];
+		assert_eq!(pixel(0, 49), [0, 0, 0, 0]);
+		assert_eq!(pixel(0, 50), [255, 0, 0, 255]);
+		assert_eq!(pixel(199, 149), [255, 0, 0, 255]);
+		assert_eq!(pixel(199, 150), [0, 0, 0, 0]);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn color_planes_default_to_1_and_can_be_overridden() {
+		let dir = temp_dir("color-planes");
+		let input = dir.join("square.png");
+		std::fs::write(&input, rgba_png(16, 16, |x, y| [x as u8, y as u8, 0, 255])).unwrap();
+		let planes = |builder: ConversionBuilder| {
+			builder.convert(&input, dir.join("out.ico")).unwrap();
+			parse_ico(dir.join("out.ico")).unwrap()[0].color_planes
+		};
+		assert_eq!(planes(ConversionBuilder::new()), 1);
+		assert_eq!(planes(ConversionBuilder::new().color_planes(0)), 0);
+		let result = ConversionBuilder::new().color_planes(2).validate(&input);
+		assert!(matches!(result, Err(PngIcoError::InvalidColorPlanes(2))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn size_formats_mix_png_and_bmp_entries() {
+		let dir = temp_dir("size-formats");
+		let (input, output) = (dir.join("square.png"), dir.join("out.ico"));
+		std::fs::write(&input, rgba_png(64, 64, |x, y| [x as u8 * 4, y as u8 * 4, 0, 255])).unwrap();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let builder = ConversionBuilder::new().size_formats(&[(64, EntryFormat::Png), (48, bmp)]);
+		builder.convert(&input, &output).unwrap();
+		let entries = parse_ico(&output).unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].width, 64);
+		assert!(entries[0].data.starts_with(&PNG_SIGNATURE));
+		assert_eq!(entries[1].width, 48);
+		// BMP entries start with the 40-byte size of their BITMAPINFOHEADER.
+		assert_eq!(entries[1].data[..4], 40u32.to_le_bytes());
+		assert_eq!(entries[0].offset, 6 + 2 * 16);
+		assert_eq!(entries[1].offset, entries[0].offset + entries[0].data.len() as u32);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn exact_output_path_keeps_the_extension() {
+		let dir = temp_dir("exact-output-path");
+		let input = dir.join("square.png");
+		std::fs::write(&input, rgba_png(16, 16, |_, _| [0, 0, 255, 255])).unwrap();
+		ConversionBuilder::new().exact_output_path(true).convert(&input, dir.join("favicon.bin")).unwrap();
+		assert_eq!(parse_ico(dir.join("favicon.bin")).unwrap()[0].width, 16);
+		assert!(!dir.join("favicon.ico").exists());
+		// By default the extension is replaced.
+		ConversionBuilder::new().convert(&input, dir.join("other.bin")).unwrap();
+		assert!(dir.join("other.ico").exists() && !dir.join("other.bin").exists());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn embed_source_keeps_the_source_bytes_beside_resized_entries() {
+		let dir = temp_dir("embed-source");
+		let input = dir.join("source.png");
+		let png = rgba_png(128, 128, |x, y| [x as u8 * 2, y as u8 * 2, 64, 255]);
+		std::fs::write(&input, &png).unwrap();
+		ConversionBuilder::new().sizes(&[16, 32]).embed_source(true).convert(&input, dir.join("out.ico")).unwrap();
+		let entries = parse_ico(dir.join("out.ico")).unwrap();
+		let widths = entries.iter().map(|entry| entry.width).collect::<Vec<_>>();
+		assert_eq!(widths, [128, 16, 32]);
+		assert_eq!(entries[0].data, png);
+		assert!(entries[1..].iter().all(|entry| entry.data != png));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn validate_plans_the_entries_without_writing() {
+		let dir = temp_dir("validate");
+		let input = dir.join("source.png");
+		std::fs::write(&input, rgba_png(64, 64, |x, y| [x as u8, y as u8, 0, 255])).unwrap();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let builder = ConversionBuilder::new().size_formats(&[(32, EntryFormat::Png), (16, bmp)]);
+		let planned = builder.validate(&input).unwrap();
+		let summary = planned.iter().map(|entry| (entry.width, entry.height, entry.format)).collect::<Vec<_>>();
+		assert_eq!(summary, [(32, 32, EntryFormat::Png), (16, 16, bmp)]);
+		// A 16x16 BMP entry is its header, 32bpp pixels and a mask of 4-byte rows.
+		assert_eq!(planned[1].len, 40 + 16 * 16 * 4 + 16 * 4);
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+		builder.convert(&input, dir.join("out.ico")).unwrap();
+		let entries = parse_ico(dir.join("out.ico")).unwrap();
+		assert_eq!(entries.iter().map(|entry| entry.data.len()).collect::<Vec<_>>(), [planned[0].len, planned[1].len]);
+		let planned = ConversionBuilder::new().sizes(&[300]).validate(&input);
+		assert!(matches!(planned, Err(PngIcoError::DimensionTooLarge(300))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn transparent_color_keys_out_the_background() {
+		let dir = temp_dir("transparent-color");
+		let input = dir.join("sprite.png");
+		// An opaque white 2x2 square in the middle of a magenta 4x4.
+		let inside = |x: u32, y: u32| (1..3).contains(&x) && (1..3).contains(&y);
+		std::fs::write(&input, rgba_png(4, 4, |x, y| if inside(x, y) { [255; 4] } else { [255, 0, 255, 255] })).unwrap();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let builder = ConversionBuilder::new().transparent_color([255, 0, 255]);
+		builder.convert(&input, dir.join("png.ico")).unwrap();
+		builder.format(bmp).convert(&input, dir.join("bmp.ico")).unwrap();
+
+		let expected = (0..16).flat_map(|i| if inside(i % 4, i / 4) { [255; 4] } else { [0; 4] }).collect::<Vec<_>>();
+		let png = parse_ico(dir.join("png.ico")).unwrap().remove(0);
+		assert_eq!(rgba(&png), expected);
+		let bmp = parse_ico(dir.join("bmp.ico")).unwrap().remove(0);
+		assert_eq!(PngParser::new().decode_bytes(&bmp.to_png().unwrap()).unwrap().to_rgba8(), expected);
+		// The AND mask follows the 4x4 BGRA pixels, one 4-byte row each, bottom row first.
+		assert_eq!(bmp.data[40 + 64..], [0xF0, 0, 0, 0, 0x90, 0, 0, 0, 0x90, 0, 0, 0, 0xF0, 0, 0, 0]);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
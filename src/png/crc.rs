@@ -0,0 +1,42 @@
+const CRC_TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut n = 0;
+	while n < 256 {
+		let mut c = n as u32;
+		let mut k = 0;
+		while k < 8 {
+			c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+			k += 1;
+		}
+		table[n] = c;
+		n += 1;
+	}
+	table
+}
+
+// The standard PNG CRC-32, computed over the concatenation of `parts`.
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+	let mut crc = 0xFFFFFFFF;
+	for part in parts {
+		for byte in part.iter() {
+			crc = CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+		}
+	}
+	crc ^ 0xFFFFFFFF
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn matches_the_standard_check_values() {
+		assert_eq!(crc32(&[b"123456789"]), 0xCBF43926);
+		// Every PNG ends with an empty IEND chunk, whose CRC covers just its name.
+		assert_eq!(crc32(&[b"IEND"]), 0xAE426082);
+		assert_eq!(crc32(&[b"IE", b"", b"ND"]), 0xAE426082);
+		assert_eq!(crc32(&[]), 0);
+	}
+}
@@ -0,0 +1,48 @@
+use alloc::vec::Vec;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+// Compresses `data` into a zlib stream. Level 0 stores the data uncompressed, 9 searches hardest for matches.
+pub(crate) fn zlib_compress(data: &[u8], level: u8) -> Vec<u8> {
+	compress_to_vec_zlib(data, level.min(9))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::inflate::zlib_decompress;
+
+	fn assert_round_trips(data: &[u8]) {
+		for level in 0..=9 {
+			let compressed = zlib_compress(data, level);
+			assert_eq!(zlib_decompress(&compressed, data.len()).unwrap(), data, "level {}", level);
+		}
+	}
+
+	#[test]
+	fn round_trips_empty_input() {
+		assert_round_trips(&[]);
+	}
+
+	#[test]
+	fn round_trips_long_runs() {
+		let data = [vec![0; 100_000], vec![7; 300], b"abcabcabc".repeat(1000)].concat();
+		assert_round_trips(&data);
+		assert!(zlib_compress(&data, 6).len() < data.len() / 50);
+	}
+
+	#[test]
+	fn round_trips_incompressible_data() {
+		// Stored blocks hold at most 65535 bytes, so this takes several of them at level 0.
+		let mut state = 0x2545_F491u32;
+		let data = (0..200_000)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+				state as u8
+			})
+			.collect::<Vec<_>>();
+		assert_round_trips(&data);
+	}
+}
@@ -0,0 +1,113 @@
+use alloc::vec::Vec;
+
+use super::crc::crc32;
+use super::deflate::zlib_compress;
+use super::filter::filter;
+use super::{ColorSpace, ColorType, Transparency, PNG_SIGNATURE};
+
+pub(crate) const DEFAULT_COMPRESSION_LEVEL: u8 = 6;
+// sRGB's gamma of 1/2.2, times 100000.
+const SRGB_GAMMA: u32 = 45455;
+
+pub(crate) fn write_chunk(out: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+	out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	out.extend_from_slice(name);
+	out.extend_from_slice(data);
+	out.extend_from_slice(&crc32(&[name, data]).to_be_bytes());
+}
+
+fn write_color_space(out: &mut Vec<u8>, color_space: Option<ColorSpace>) {
+	match color_space {
+		Some(ColorSpace::Srgb(intent)) => {
+			write_chunk(out, b"sRGB", &[intent]);
+			// The spec asks for the matching gAMA alongside sRGB, for decoders that don't know sRGB.
+			write_chunk(out, b"gAMA", &SRGB_GAMMA.to_be_bytes());
+		}
+		Some(ColorSpace::Gamma(gamma)) => write_chunk(out, b"gAMA", &gamma.to_be_bytes()),
+		None => {}
+	}
+}
+
+// Encodes an 8-bit RGBA buffer as a non-interlaced truecolor+alpha PNG.
+pub(crate) fn encode_png(rgba: &[u8], width: u32, height: u32, color_space: Option<ColorSpace>, level: u8) -> Vec<u8> {
+	encode_png_as(rgba, width, height, ColorType::TruecolorAlpha, None, color_space, level)
+}
+
+// Encodes packed 8-bit samples of a non-indexed color type as a non-interlaced PNG.
+// Gray and RGB transparency is written as a tRNS chunk, and the color space as an sRGB or gAMA chunk.
+pub(crate) fn encode_png_as(
+	data: &[u8],
+	width: u32,
+	height: u32,
+	color: ColorType,
+	transparency: Option<&Transparency>,
+	color_space: Option<ColorSpace>,
+	level: u8,
+) -> Vec<u8> {
+	let mut header = Vec::with_capacity(13);
+	header.extend_from_slice(&width.to_be_bytes());
+	header.extend_from_slice(&height.to_be_bytes());
+	header.extend_from_slice(&[8, color.to_u8(), 0, 0, 0]);
+
+	let mut out = PNG_SIGNATURE.to_vec();
+	write_chunk(&mut out, b"IHDR", &header);
+	write_color_space(&mut out, color_space);
+	match transparency {
+		Some(Transparency::Gray(gray)) => write_chunk(&mut out, b"tRNS", &gray.to_be_bytes()),
+		Some(&Transparency::Rgb(r, g, b)) => {
+			let samples = [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat();
+			write_chunk(&mut out, b"tRNS", &samples)
+		}
+		_ => {}
+	}
+	let bits_per_pixel = color.channel_count() * 8;
+	write_chunk(&mut out, b"IDAT", &zlib_compress(&filter(data, width, height, bits_per_pixel), level));
+	write_chunk(&mut out, b"IEND", &[]);
+	out
+}
+
+// Encodes one palette index per pixel as a non-interlaced indexed PNG of `bit_depth` bits per index, which must be
+// 1, 2, 4 or 8 and wide enough for every index. The palette's alpha values are written as a tRNS chunk unless they
+// are all opaque.
+#[cfg(feature = "std")]
+pub(crate) fn encode_indexed_png(
+	indices: &[u8],
+	width: u32,
+	height: u32,
+	palette: &[[u8; 4]],
+	bit_depth: u8,
+	color_space: Option<ColorSpace>,
+	level: u8,
+) -> Vec<u8> {
+	let mut header = Vec::with_capacity(13);
+	header.extend_from_slice(&width.to_be_bytes());
+	header.extend_from_slice(&height.to_be_bytes());
+	header.extend_from_slice(&[bit_depth, ColorType::Indexed.to_u8(), 0, 0, 0]);
+
+	let mut out = PNG_SIGNATURE.to_vec();
+	write_chunk(&mut out, b"IHDR", &header);
+	write_color_space(&mut out, color_space);
+	let colors = palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect::<Vec<_>>();
+	write_chunk(&mut out, b"PLTE", &colors);
+	// Entries past the last translucent one are opaque, so they can be left out.
+	let alpha_len = palette.iter().rposition(|color| color[3] != 255).map_or(0, |last| last + 1);
+	if alpha_len > 0 {
+		let alpha = palette[..alpha_len].iter().map(|color| color[3]).collect::<Vec<_>>();
+		write_chunk(&mut out, b"tRNS", &alpha);
+	}
+
+	// Rows are packed most significant bits first, each starting on a byte boundary.
+	let per_byte = 8 / bit_depth as usize;
+	let mut packed = Vec::with_capacity(indices.len().div_ceil(per_byte));
+	for row in indices.chunks(width.max(1) as usize) {
+		for group in row.chunks(per_byte) {
+			let byte = group.iter().enumerate().fold(0, |byte, (i, &index)| {
+				byte | index << (8 - bit_depth as usize * (i + 1))
+			});
+			packed.push(byte);
+		}
+	}
+	write_chunk(&mut out, b"IDAT", &zlib_compress(&filter(&packed, width, height, bit_depth as u32), level));
+	write_chunk(&mut out, b"IEND", &[]);
+	out
+}
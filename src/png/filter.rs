@@ -0,0 +1,214 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{PngIcoError, Result};
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+	let p = a as i16 + b as i16 - c as i16;
+	let pa = (p - a as i16).abs();
+	let pb = (p - b as i16).abs();
+	let pc = (p - c as i16).abs();
+	if pa <= pb && pa <= pc {
+		a
+	} else if pb <= pc {
+		b
+	} else {
+		c
+	}
+}
+
+fn unfilter_row(filter: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> Result<()> {
+	match filter {
+		0 => {}
+		1 => {
+			for i in bpp..row.len() {
+				row[i] = row[i].wrapping_add(row[i - bpp]);
+			}
+		}
+		2 => {
+			for (byte, up) in row.iter_mut().zip(prev) {
+				*byte = byte.wrapping_add(*up);
+			}
+		}
+		3 => {
+			for i in 0..row.len() {
+				let left = if i >= bpp { row[i - bpp] } else { 0 };
+				row[i] = row[i].wrapping_add(((left as u16 + prev[i] as u16) / 2) as u8);
+			}
+		}
+		4 => {
+			for i in 0..row.len() {
+				let (left, up_left) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+				row[i] = row[i].wrapping_add(paeth(left, prev[i], up_left));
+			}
+		}
+		_ => return Err(PngIcoError::InvalidImageData("unknown scanline filter type")),
+	}
+	Ok(())
+}
+
+// The number of scanlines that used each filter type, indexed by type.
+pub(crate) type FilterCounts = [u32; 5];
+
+// The length of `height` filtered rows, each a filter-type byte followed by the row's packed samples.
+pub(crate) fn filtered_len(width: u32, height: u32, bits_per_pixel: u32) -> usize {
+	((width as usize * bits_per_pixel as usize).div_ceil(8) + 1) * height as usize
+}
+
+// Reverses the per-scanline filters, returning the packed rows without their filter-type bytes, and adds each row's
+// filter type to `counts`.
+pub(crate) fn unfilter(
+	data: &[u8],
+	width: u32,
+	height: u32,
+	bits_per_pixel: u32,
+	counts: &mut FilterCounts,
+) -> Result<Vec<u8>> {
+	// Filters operate on whole bytes, so sub-byte pixels use a stride of 1.
+	let bpp = bits_per_pixel.div_ceil(8) as usize;
+	let row_len = (width as usize * bits_per_pixel as usize).div_ceil(8);
+	let height = height as usize;
+	if data.len() < filtered_len(width, height as u32, bits_per_pixel) {
+		return Err(PngIcoError::InvalidImageData("not enough image data for the declared size"));
+	}
+
+	let mut out = vec![0u8; row_len * height];
+	let zero_row = vec![0u8; row_len];
+	for y in 0..height {
+		let src = &data[y * (row_len + 1)..(y + 1) * (row_len + 1)];
+		let (done, rest) = out.split_at_mut(y * row_len);
+		let row = &mut rest[..row_len];
+		row.copy_from_slice(&src[1..]);
+		let prev = if y == 0 { &zero_row[..] } else { &done[(y - 1) * row_len..] };
+		unfilter_row(src[0], row, prev, bpp)?;
+		counts[src[0] as usize] += 1;
+	}
+	Ok(out)
+}
+
+fn filter_row(filter: u8, row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+	out.push(filter);
+	for i in 0..row.len() {
+		let (left, up_left) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+		let up = prev[i];
+		let predictor = match filter {
+			0 => 0,
+			1 => left,
+			2 => up,
+			3 => ((left as u16 + up as u16) / 2) as u8,
+			_ => paeth(left, up, up_left),
+		};
+		out.push(row[i].wrapping_sub(predictor));
+	}
+}
+
+// Filters each scanline with whichever filter type gives the smallest sum of absolute differences.
+pub(crate) fn filter(data: &[u8], width: u32, height: u32, bits_per_pixel: u32) -> Vec<u8> {
+	let bpp = bits_per_pixel.div_ceil(8) as usize;
+	let row_len = (width as usize * bits_per_pixel as usize).div_ceil(8);
+	let mut out = Vec::with_capacity((row_len + 1) * height as usize);
+	let zero_row = vec![0u8; row_len];
+	let mut candidate = Vec::with_capacity(row_len + 1);
+	let mut best = Vec::with_capacity(row_len + 1);
+	for y in 0..height as usize {
+		let row = &data[y * row_len..(y + 1) * row_len];
+		let prev = if y == 0 { &zero_row[..] } else { &data[(y - 1) * row_len..y * row_len] };
+		let mut best_score = u64::MAX;
+		for filter in 0..5 {
+			candidate.clear();
+			filter_row(filter, row, prev, bpp, &mut candidate);
+			let score = candidate[1..].iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum();
+			if score < best_score {
+				best_score = score;
+				core::mem::swap(&mut best, &mut candidate);
+			}
+		}
+		out.extend_from_slice(&best);
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Two rows of two 8-bit grayscale+alpha pixels, so each filter's left neighbour is two bytes back.
+	const PIXELS: [u8; 8] = [10, 20, 30, 40, 50, 60, 70, 80];
+	const BITS_PER_PIXEL: u32 = 16;
+
+	// The second row as each filter type stores it, worked out by hand from the spec. The first row is stored with
+	// filter 0.
+	const FILTERED: [[u8; 4]; 5] = [
+		[50, 60, 70, 80],
+		// Sub: minus the byte to the left, which is 0 for the first pixel.
+		[50, 60, 20, 20],
+		// Up: minus the byte above.
+		[40, 40, 40, 40],
+		// Average: minus the floor of the mean of left and up, so 50 - 5, 60 - 10, 70 - 40 and 80 - 50.
+		[45, 50, 30, 30],
+		// Paeth: the first pixel predicts from above (b), the second from its left (a).
+		[40, 40, 20, 20],
+	];
+
+	fn stored(filter: u8) -> Vec<u8> {
+		[&[0][..], &PIXELS[..4], &[filter], &FILTERED[filter as usize]].concat()
+	}
+
+	#[test]
+	fn unfilters_each_filter_type() {
+		for filter in 0..5 {
+			let mut counts = FilterCounts::default();
+			let unfiltered = unfilter(&stored(filter), 2, 2, BITS_PER_PIXEL, &mut counts).unwrap();
+			assert_eq!(unfiltered, PIXELS, "filter {}", filter);
+			let mut expected = [0; 5];
+			expected[0] += 1;
+			expected[filter as usize] += 1;
+			assert_eq!(counts, expected);
+		}
+	}
+
+	#[test]
+	fn filters_each_filter_type() {
+		for filter in 0..5 {
+			let mut out = Vec::new();
+			filter_row(filter, &PIXELS[4..], &PIXELS[..4], 2, &mut out);
+			assert_eq!(out, stored(filter)[5..], "filter {}", filter);
+		}
+	}
+
+	#[test]
+	fn paeth_picks_the_nearest_predictor() {
+		// p = a + b - c; the predictor closest to p wins, ties going to a, then b.
+		assert_eq!(paeth(20, 10, 10), 20);
+		assert_eq!(paeth(10, 20, 10), 20);
+		assert_eq!(paeth(5, 15, 10), 10);
+		assert_eq!(paeth(10, 10, 0), 10);
+		assert_eq!(paeth(10, 20, 30), 10);
+		// The sum is computed without wrapping.
+		assert_eq!(paeth(200, 200, 0), 200);
+		assert_eq!(paeth(0, 0, 255), 0);
+	}
+
+	#[test]
+	fn unfilters_sub_byte_pixels_with_a_one_byte_stride() {
+		// A 16-pixel 1-bit row is 2 bytes, and Sub adds the previous byte rather than the previous pixel.
+		let mut counts = FilterCounts::default();
+		assert_eq!(unfilter(&[1, 0x0F, 0xF1], 16, 1, 1, &mut counts).unwrap(), [0x0F, 0x00]);
+	}
+
+	#[test]
+	fn rejects_unknown_filter_types() {
+		let mut counts = FilterCounts::default();
+		let result = unfilter(&[5, 0, 0], 1, 1, BITS_PER_PIXEL, &mut counts);
+		assert!(matches!(result, Err(PngIcoError::InvalidImageData(_))));
+	}
+
+	#[test]
+	fn picks_the_filter_with_the_smallest_residuals() {
+		// The second row repeats the first, so Up leaves only zeros.
+		let rows = [PIXELS[..4].to_vec(), PIXELS[..4].to_vec()].concat();
+		let filtered = filter(&rows, 2, 2, BITS_PER_PIXEL);
+		assert_eq!(filtered[5..], [2, 0, 0, 0, 0]);
+		let mut counts = FilterCounts::default();
+		assert_eq!(unfilter(&filtered, 2, 2, BITS_PER_PIXEL, &mut counts).unwrap(), rows);
+	}
+}
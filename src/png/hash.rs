@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+use super::EncodedPng;
+
+/// The SHA-256 of a `width` by `height` image of 8-bit RGBA `pixels`, for telling whether two images are the same.
+/// The big-endian width and height are hashed before the pixels, so images with the same bytes but different
+/// dimensions hash differently.
+pub fn rgba_hash(pixels: &[u8], width: u32, height: u32) -> [u8; 32] {
+	let mut hasher = Sha256::new();
+	hasher.update(width.to_be_bytes());
+	hasher.update(height.to_be_bytes());
+	hasher.update(pixels);
+	hasher.finalize().into()
+}
+
+impl EncodedPng {
+	/// The [`rgba_hash`] of the image converted to 8-bit RGBA. It depends only on the pixels, so the same image
+	/// hashes the same whatever its color type, compression or ancillary chunks, and a build tool can skip
+	/// regenerating an icon whose source hasn't changed. 16-bit images are reduced to 8 bits first.
+	pub fn image_hash(&self) -> [u8; 32] {
+		let mut hasher = Sha256::new();
+		hasher.update(self.metadata.width.to_be_bytes());
+		hasher.update(self.metadata.height.to_be_bytes());
+		for (_, _, rgba) in self.pixels() {
+			hasher.update(rgba);
+		}
+		hasher.finalize().into()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::rgba_png;
+	use crate::png::PngParser;
+
+	#[test]
+	fn identical_images_hash_equal() {
+		let pixels = [10, 20, 30, 255].repeat(16);
+		assert_eq!(rgba_hash(&pixels, 4, 4), rgba_hash(&pixels.clone(), 4, 4));
+		let mut changed = pixels.clone();
+		changed[4 * 5 + 2] ^= 1;
+		assert_ne!(rgba_hash(&changed, 4, 4), rgba_hash(&pixels, 4, 4));
+		assert_ne!(rgba_hash(&pixels, 2, 8), rgba_hash(&pixels, 4, 4));
+	}
+
+	#[test]
+	fn image_hash_is_the_hash_of_the_decoded_pixels() {
+		let pixel = |x: u32, y: u32| [x as u8 * 40, y as u8 * 40, 7, 255];
+		let png = PngParser::new().decode_bytes(&rgba_png(5, 3, pixel)).unwrap();
+		let rgba = (0..15).flat_map(|i| pixel(i % 5, i / 5)).collect::<Vec<_>>();
+		assert_eq!(png.image_hash(), rgba_hash(&rgba, 5, 3));
+	}
+}
@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use super::PngMetadata;
+use crate::{PngIcoError, Result};
+
+const ICNS_HEADER_SIZE: usize = 8;
+
+// The OSTypes that hold a PNG of each size. The second is the retina type that shows the same pixels at half size.
+fn icns_types(size: u32) -> Result<(&'static [u8; 4], Option<&'static [u8; 4]>)> {
+	match size {
+		16 => Ok((b"icp4", None)),
+		32 => Ok((b"icp5", Some(b"ic11"))),
+		64 => Ok((b"icp6", Some(b"ic12"))),
+		128 => Ok((b"ic07", None)),
+		256 => Ok((b"ic08", Some(b"ic13"))),
+		512 => Ok((b"ic09", Some(b"ic14"))),
+		1024 => Ok((b"ic10", None)),
+		size => Err(PngIcoError::InvalidSize(size)),
+	}
+}
+
+fn write_icns_element(buf: &mut Vec<u8>, os_type: &[u8; 4], png: &[u8]) {
+	buf.extend_from_slice(os_type);
+	// The length includes the 8-byte element header.
+	buf.extend_from_slice(&((ICNS_HEADER_SIZE + png.len()) as u32).to_be_bytes());
+	buf.extend_from_slice(png);
+}
+
+/// Builds an ICNS container from square PNGs of 16, 32, 64, 128, 256, 512 or 1024 pixels.
+/// Sizes that double as the retina variant of a smaller size are stored under both OSTypes.
+pub fn encode_icns(entries: &[(&PngMetadata, &[u8])]) -> Result<Vec<u8>> {
+	if entries.is_empty() {
+		return Err(PngIcoError::NoImages);
+	}
+	let mut body = Vec::new();
+	for (png, data) in entries {
+		if png.width != png.height {
+			return Err(PngIcoError::NotSquare(png.width, png.height));
+		}
+		let (os_type, retina_type) = icns_types(png.width)?;
+		write_icns_element(&mut body, os_type, data);
+		if let Some(retina_type) = retina_type {
+			write_icns_element(&mut body, retina_type, data);
+		}
+	}
+
+	let mut buf = Vec::with_capacity(ICNS_HEADER_SIZE + body.len());
+	buf.extend_from_slice(b"icns");
+	buf.extend_from_slice(&((ICNS_HEADER_SIZE + body.len()) as u32).to_be_bytes());
+	buf.extend_from_slice(&body);
+	Ok(buf)
+}
+
+/// Writes a macOS .icns file from the PNGs at the given paths. The extension of `out_path` is replaced with .icns.
+#[cfg(feature = "std")]
+pub fn write_icns(out_path: impl AsRef<Path>, images: &[(PngMetadata, PathBuf)]) -> Result<()> {
+	let payloads = images
+		.iter()
+		.map(|(_, path)| std::fs::read(path).map_err(PngIcoError::UnreadableFile))
+		.collect::<Result<Vec<_>>>()?;
+	let entries = images
+		.iter()
+		.zip(&payloads)
+		.map(|((png, _), payload)| (png, &payload[..]))
+		.collect::<Vec<_>>();
+	let buf = encode_icns(&entries)?;
+	std::fs::write(out_path.as_ref().with_extension("icns"), buf).map_err(PngIcoError::UnwritableFile)
+}
@@ -0,0 +1,575 @@
+#[cfg(feature = "std")]
+use alloc::{format, vec};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use super::encoder::{encode_png, DEFAULT_COMPRESSION_LEVEL};
+#[cfg(feature = "std")]
+use super::ico_writer::{encode_entries, write_output};
+#[cfg(feature = "std")]
+use super::{ColorType, PngMetadata};
+use super::{PngParser, MAX_ICON_DIMENSION, PNG_SIGNATURE};
+use crate::bmp::decode_dib;
+use crate::{PngIcoError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStorage {
+	/// The entry holds a complete PNG file.
+	Png,
+	/// The entry holds a BITMAPINFOHEADER DIB followed by the pixel data and AND mask.
+	Bmp,
+}
+
+#[derive(Debug, Clone)]
+pub struct IcoEntry {
+	pub width: u32,
+	pub height: u32,
+	pub color_planes: u16,
+	pub bits_per_pixel: u16,
+	/// The cursor hotspot, which cursor files store in place of the color planes and bits per pixel.
+	pub hotspot: Option<(u16, u16)>,
+	pub offset: u32,
+	pub storage: EntryStorage,
+	pub data: Vec<u8>,
+}
+
+impl IcoEntry {
+	/// The entry as a complete PNG file. PNG entries are returned verbatim, while BMP entries are decoded and re-encoded.
+	pub fn to_png(&self) -> Result<Vec<u8>> {
+		match self.storage {
+			EntryStorage::Png => Ok(self.data.clone()),
+			EntryStorage::Bmp => {
+				let (width, height, rgba) = decode_dib(&self.data, true)?;
+				Ok(encode_png(&rgba, width, height, None, DEFAULT_COMPRESSION_LEVEL))
+			}
+		}
+	}
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+	u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+	u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+// A stored dimension of 0 means 256.
+fn decode_dimension(byte: u8) -> u32 {
+	if byte == 0 {
+		MAX_ICON_DIMENSION
+	} else {
+		byte as u32
+	}
+}
+
+// Checks the 6-byte ICONDIR at the start of `data`, and returns the image type and count.
+fn read_icondir(data: &[u8]) -> Result<(u16, usize)> {
+	if data.len() < 6 {
+		return Err(PngIcoError::InvalidIco("file is too short to contain an ICONDIR"));
+	}
+	if read_u16(data, 0) != 0 {
+		return Err(PngIcoError::InvalidIco("reserved field is not 0"));
+	}
+	let image_type = read_u16(data, 2);
+	if !matches!(image_type, 1 | 2) {
+		return Err(PngIcoError::InvalidIco("image type is neither icon nor cursor"));
+	}
+	Ok((image_type, read_u16(data, 4) as usize))
+}
+
+pub fn parse_ico_bytes(data: &[u8]) -> Result<Vec<IcoEntry>> {
+	let (image_type, count) = read_icondir(data)?;
+	if data.len() < 6 + 16 * count {
+		return Err(PngIcoError::InvalidIco("directory runs past the end of the file"));
+	}
+
+	(0..count)
+		.map(|i| {
+			let entry = &data[6 + 16 * i..6 + 16 * (i + 1)];
+			let size = read_u32(entry, 8);
+			let offset = read_u32(entry, 12);
+			// Checked without adding, so a huge offset or size can't overflow on 32-bit targets either.
+			let payload = data
+				.get(offset as usize..)
+				.and_then(|rest| rest.get(..size as usize))
+				.ok_or(PngIcoError::EntryOutOfBounds(i, offset, size, data.len()))?;
+			let storage = if payload.starts_with(&PNG_SIGNATURE) {
+				EntryStorage::Png
+			} else {
+				EntryStorage::Bmp
+			};
+			let (color_planes, bits_per_pixel) = (read_u16(entry, 4), read_u16(entry, 6));
+			Ok(IcoEntry {
+				width: decode_dimension(entry[0]),
+				height: decode_dimension(entry[1]),
+				color_planes,
+				bits_per_pixel,
+				hotspot: if image_type == 2 {
+					Some((color_planes, bits_per_pixel))
+				} else {
+					None
+				},
+				offset,
+				storage,
+				data: payload.to_vec(),
+			})
+		})
+		.collect()
+}
+
+fn invalid_at(offset: usize, reason: &'static str) -> PngIcoError {
+	PngIcoError::InvalidIcoAt(offset, reason)
+}
+
+// A BITMAPINFOHEADER (or a later, larger version) with one color plane and a standard bit count.
+fn is_plausible_dib(payload: &[u8]) -> bool {
+	payload.len() >= 40
+		&& read_u32(payload, 0) >= 40
+		&& read_u16(payload, 12) == 1
+		&& matches!(read_u16(payload, 14), 1 | 4 | 8 | 16 | 24 | 32)
+}
+
+/// Checks the structure of an ICO or CUR file, returning the first problem found along with its byte offset.
+/// Every entry must lie within the file and hold either a PNG with a valid header or a plausible BMP DIB.
+pub fn verify_ico_bytes(data: &[u8]) -> Result<()> {
+	if data.len() < 6 {
+		return Err(invalid_at(0, "file is too short to contain an ICONDIR"));
+	}
+	if read_u16(data, 0) != 0 {
+		return Err(invalid_at(0, "reserved field is not 0"));
+	}
+	if !matches!(read_u16(data, 2), 1 | 2) {
+		return Err(invalid_at(2, "image type is neither icon nor cursor"));
+	}
+	let count = read_u16(data, 4) as usize;
+	if count == 0 {
+		return Err(invalid_at(4, "image count is 0"));
+	}
+	if data.len() < 6 + 16 * count {
+		return Err(invalid_at(4, "image count is larger than the directory in the file"));
+	}
+
+	for i in 0..count {
+		let entry = 6 + 16 * i;
+		let size = read_u32(data, entry + 8) as usize;
+		let offset = read_u32(data, entry + 12) as usize;
+		if offset < 6 + 16 * count {
+			return Err(invalid_at(entry + 12, "entry data overlaps the directory"));
+		}
+		let payload = offset
+			.checked_add(size)
+			.and_then(|end| data.get(offset..end))
+			.ok_or_else(|| invalid_at(entry + 8, "entry data runs past the end of the file"))?;
+		if payload.starts_with(&PNG_SIGNATURE) {
+			if PngParser::new().parse_header_bytes(payload).is_err() {
+				return Err(invalid_at(offset, "entry PNG has an invalid header"));
+			}
+		} else if !is_plausible_dib(payload) {
+			return Err(invalid_at(offset, "entry is neither a PNG nor a BMP DIB"));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(feature = "std")]
+pub fn verify_ico(path: impl AsRef<Path>) -> Result<()> {
+	let data = std::fs::read(path).map_err(PngIcoError::UnreadableFile)?;
+	verify_ico_bytes(&data)
+}
+
+#[cfg(feature = "std")]
+pub fn parse_ico(path: impl AsRef<Path>) -> Result<Vec<IcoEntry>> {
+	let data = std::fs::read(path).map_err(PngIcoError::UnreadableFile)?;
+	parse_ico_bytes(&data)
+}
+
+/// The width, height and bits per pixel of each entry of the ICO at `path`. Only the header and directory are read,
+/// never the image data, so this is fast however large the file is. Cursors store their hotspot in place of the bit
+/// count, so their entries report 0 bits per pixel.
+#[cfg(feature = "std")]
+pub fn list_ico_sizes(path: impl AsRef<Path>) -> Result<Vec<(u16, u16, u16)>> {
+	let mut file = File::open(path).map_err(PngIcoError::UnreadableFile)?;
+	let mut header = [0; 6];
+	read_exact(&mut file, &mut header, "file is too short to contain an ICONDIR")?;
+	let (image_type, count) = read_icondir(&header)?;
+	let mut directory = vec![0; 16 * count];
+	read_exact(&mut file, &mut directory, "directory runs past the end of the file")?;
+	Ok(directory
+		.chunks(16)
+		.map(|entry| {
+			let bits_per_pixel = if image_type == 2 { 0 } else { read_u16(entry, 6) };
+			(decode_dimension(entry[0]) as u16, decode_dimension(entry[1]) as u16, bits_per_pixel)
+		})
+		.collect())
+}
+
+/// The PNG of the `width` by `height` entry of the ICO at `ico_path`, exactly as it is stored. Only the directory
+/// and that entry are read, and nothing is decoded, so this is faster than [`IcoEntry::to_png`] and never loses
+/// anything. None if the file has no entry of that size, or only BMP ones; if several are PNGs, the first is returned.
+#[cfg(feature = "std")]
+pub fn get_entry_png(ico_path: impl AsRef<Path>, width: u32, height: u32) -> Result<Option<Vec<u8>>> {
+	let mut file = File::open(ico_path).map_err(PngIcoError::UnreadableFile)?;
+	let file_len = file.metadata().map_err(PngIcoError::UnreadableFile)?.len();
+	let mut header = [0; 6];
+	read_exact(&mut file, &mut header, "file is too short to contain an ICONDIR")?;
+	let (_, count) = read_icondir(&header)?;
+	let mut directory = vec![0; 16 * count];
+	read_exact(&mut file, &mut directory, "directory runs past the end of the file")?;
+	for (index, entry) in directory.chunks(16).enumerate() {
+		if (decode_dimension(entry[0]), decode_dimension(entry[1])) != (width, height) {
+			continue;
+		}
+		let (size, offset) = (read_u32(entry, 8), read_u32(entry, 12));
+		// Checked before allocating, so a corrupt size can't ask for more memory than the file holds.
+		if offset as u64 + size as u64 > file_len {
+			return Err(PngIcoError::EntryOutOfBounds(index, offset, size, file_len as usize));
+		}
+		file.seek(SeekFrom::Start(offset as u64)).map_err(PngIcoError::UnreadableFile)?;
+		let mut payload = vec![0; size as usize];
+		read_exact(&mut file, &mut payload, "entry data runs past the end of the file")?;
+		if payload.starts_with(&PNG_SIGNATURE) {
+			return Ok(Some(payload));
+		}
+	}
+	Ok(None)
+}
+
+// Fills `buf` from `file`, reporting a file that ends first as an invalid ICO.
+#[cfg(feature = "std")]
+fn read_exact(file: &mut File, buf: &mut [u8], too_short: &'static str) -> Result<()> {
+	file.read_exact(buf).map_err(|err| match err.kind() {
+		io::ErrorKind::UnexpectedEof => PngIcoError::InvalidIco(too_short),
+		_ => PngIcoError::UnreadableFile(err),
+	})
+}
+
+/// Writes every entry of an ICO into `out_dir` as `icon_{w}x{h}.png`, returning the written paths.
+/// PNG entries are copied verbatim, while BMP entries are decoded and converted to PNG. Entries that share a size are
+/// told apart by their bits per pixel, as `icon_{w}x{h}_{bpp}bpp.png`, and if that's shared too by their index in the
+/// directory, as `icon_{w}x{h}_{bpp}bpp_{index}.png`.
+#[cfg(feature = "std")]
+pub fn extract_pngs(ico_path: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+	let out_dir = out_dir.as_ref();
+	let entries = parse_ico(ico_path)?;
+	let keys = entries
+		.iter()
+		.map(|entry| Ok((entry.width, entry.height, entry_metadata(entry)?.bits_per_pixel()?)))
+		.collect::<Result<Vec<_>>>()?;
+	let count = |matches: &dyn Fn(&(u32, u32, u32)) -> bool| keys.iter().filter(|key| matches(key)).count();
+	entries
+		.into_iter()
+		.zip(&keys)
+		.enumerate()
+		.map(|(index, (entry, &key))| {
+			let (width, height, bits_per_pixel) = key;
+			let name = if count(&|other| other.0 == width && other.1 == height) == 1 {
+				format!("icon_{}x{}.png", width, height)
+			} else if count(&|other| *other == key) == 1 {
+				format!("icon_{}x{}_{}bpp.png", width, height, bits_per_pixel)
+			} else {
+				format!("icon_{}x{}_{}bpp_{}.png", width, height, bits_per_pixel, index)
+			};
+			let path = out_dir.join(name);
+			std::fs::write(&path, entry.to_png()?).map_err(PngIcoError::UnwritableFile)?;
+			Ok(path)
+		})
+		.collect()
+}
+
+// An entry of a merged ICO, with the width, height and bits per pixel it's deduplicated by.
+#[cfg(feature = "std")]
+type MergedEntry = ((u32, u32, u32), PngMetadata, Vec<u8>);
+
+// A header that describes the entry the way its directory entry needs to. The directory only records the size, bit
+// count and palette size, so BMP entries get whichever PNG color type has their DIB's bit count.
+#[cfg(feature = "std")]
+fn entry_metadata(entry: &IcoEntry) -> Result<PngMetadata> {
+	if entry.storage == EntryStorage::Png {
+		return PngParser::new().parse_header_bytes(&entry.data);
+	}
+	if entry.data.len() < 16 {
+		return Err(PngIcoError::InvalidIco("BMP entry is too short to contain a BITMAPINFOHEADER"));
+	}
+	let (color_type, bit_depth) = match read_u16(&entry.data, 14) {
+		bits @ (1 | 2 | 4 | 8) => (ColorType::Indexed, bits as u8),
+		16 => (ColorType::GrayscaleAlpha, 8),
+		24 => (ColorType::Truecolor, 8),
+		32 => (ColorType::TruecolorAlpha, 8),
+		_ => return Err(PngIcoError::InvalidIco("BMP entry has an unsupported bit count")),
+	};
+	Ok(PngMetadata {
+		width: entry.width,
+		height: entry.height,
+		bit_depth,
+		color_type: color_type.to_u8(),
+		compression_method: 0,
+		filter_method: 0,
+		interlace_method: 0,
+	})
+}
+
+/// Combines the entries of the ICOs at `inputs` into a single ICO at `output`, whose extension is replaced with .ico.
+/// Entries with the same width, height and bits per pixel are only kept once. If two inputs have such an entry with
+/// different data, the later input's wins, in the earlier one's place, with a warning through the `log` crate if the
+/// `log` feature is enabled.
+#[cfg(feature = "std")]
+pub fn merge_icos(inputs: &[PathBuf], output: impl AsRef<Path>) -> Result<()> {
+	let mut entries: Vec<MergedEntry> = Vec::new();
+	for input in inputs {
+		for entry in parse_ico(input)? {
+			let png = entry_metadata(&entry)?;
+			let key = (png.width(), png.height(), png.bits_per_pixel()?);
+			match entries.iter_mut().find(|(other, _, _)| *other == key) {
+				None => entries.push((key, png, entry.data)),
+				Some((_, _, data)) if *data == entry.data => {}
+				Some(existing) => {
+					#[cfg(feature = "log")]
+					log::warn!(
+						"{} replaces the {}x{} {}bpp entry of an earlier input",
+						input.display(),
+						key.0,
+						key.1,
+						key.2
+					);
+					*existing = (key, png, entry.data);
+				}
+			}
+		}
+	}
+	let entries = entries.iter().map(|(_, png, data)| (png, &data[..])).collect::<Vec<_>>();
+	let buf = encode_entries(&entries)?;
+	write_output(output, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::temp_dir;
+	use crate::png::testing::decode_rgba;
+	use crate::png::{
+		encode_ico_from_bytes, encode_ico_from_rgba, write_ico, write_ico_with_format, BmpOptions, ConversionBuilder,
+		EntryFormat,
+	};
+
+	fn solid_ico(size: u32, rgba: [u8; 4]) -> Vec<u8> {
+		encode_ico_from_rgba(&rgba.repeat((size * size) as usize), size, size).unwrap()
+	}
+
+	#[test]
+	fn merges_entries_of_different_sizes() {
+		let dir = temp_dir("merge-icos");
+		let (small, large) = (dir.join("small.ico"), dir.join("large.ico"));
+		std::fs::write(&small, solid_ico(16, [255, 0, 0, 255])).unwrap();
+		std::fs::write(&large, solid_ico(256, [0, 0, 255, 255])).unwrap();
+		merge_icos(&[small.clone(), large.clone(), small], dir.join("merged")).unwrap();
+		let merged = parse_ico(dir.join("merged.ico")).unwrap();
+		let sizes = merged.iter().map(|entry| (entry.width, entry.height)).collect::<Vec<_>>();
+		assert_eq!(sizes, [(16, 16), (256, 256)]);
+		assert_eq!(merged[1].data, parse_ico(&large).unwrap()[0].data);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn later_inputs_replace_conflicting_entries() {
+		let dir = temp_dir("merge-conflict");
+		let (red, green, large) = (dir.join("red.ico"), dir.join("green.ico"), dir.join("large.ico"));
+		std::fs::write(&red, solid_ico(16, [255, 0, 0, 255])).unwrap();
+		std::fs::write(&green, solid_ico(16, [0, 255, 0, 255])).unwrap();
+		std::fs::write(&large, solid_ico(32, [0, 0, 255, 255])).unwrap();
+		let merge = || merge_icos(&[red.clone(), large.clone(), green.clone()], dir.join("merged")).unwrap();
+		#[cfg(feature = "log")]
+		{
+			let warnings = crate::png::test_support::captured_warnings(merge);
+			assert_eq!(warnings.len(), 1);
+			assert!(warnings[0].contains("16x16 32bpp"), "{}", warnings[0]);
+		}
+		#[cfg(not(feature = "log"))]
+		merge();
+		let merged = parse_ico(dir.join("merged.ico")).unwrap();
+		assert_eq!(merged.iter().map(|entry| entry.width).collect::<Vec<_>>(), [16, 32]);
+		assert_eq!(merged[0].data, parse_ico(&green).unwrap()[0].data);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	fn sample(name: &str) -> Vec<u8> {
+		crate::png::testing::sample_pngs().into_iter().find(|(sample, _)| sample == name).unwrap().1
+	}
+
+	#[test]
+	fn extracts_every_entry() {
+		let dir = temp_dir("extract-pngs");
+		let (small, large) = (solid_ico(16, [255, 0, 0, 255]), solid_ico(32, [0, 0, 255, 128]));
+		let pngs = [&small[22..], &large[22..]];
+		std::fs::write(dir.join("two.ico"), encode_ico_from_bytes(&pngs, EntryFormat::Png).unwrap()).unwrap();
+		let paths = extract_pngs(dir.join("two.ico"), &dir).unwrap();
+		assert_eq!(paths, [dir.join("icon_16x16.png"), dir.join("icon_32x32.png")]);
+		for (path, png) in paths.iter().zip(pngs) {
+			let extracted = std::fs::read(path).unwrap();
+			assert_eq!(decode_rgba(&extracted).unwrap(), decode_rgba(png).unwrap());
+		}
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn names_entries_of_the_same_size_apart() {
+		let dir = temp_dir("extract-same-size");
+		let names = ["grayscale 8-bit", "truecolor+alpha 8-bit", "truecolor+alpha 8-bit interlaced"];
+		let pngs = names.map(sample);
+		let pngs = pngs.iter().map(|png| &png[..]).collect::<Vec<_>>();
+		std::fs::write(dir.join("same.ico"), encode_ico_from_bytes(&pngs, EntryFormat::Png).unwrap()).unwrap();
+		let paths = extract_pngs(dir.join("same.ico"), &dir).unwrap();
+		let names = paths.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>();
+		assert_eq!(names, ["icon_13x11_8bpp.png", "icon_13x11_32bpp_1.png", "icon_13x11_32bpp_2.png"]);
+		for (path, png) in paths.iter().zip(pngs) {
+			assert_eq!(decode_rgba(&std::fs::read(path).unwrap()).unwrap(), decode_rgba(png).unwrap());
+		}
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reads_back_what_write_ico_wrote() {
+		let dir = temp_dir("parse-ico");
+		let rgba = (0..48 * 48).flat_map(|i| [i as u8, (i / 48) as u8, 7, 200]).collect::<Vec<_>>();
+		let png = encode_ico_from_rgba(&rgba, 48, 48).unwrap()[22..].to_vec();
+		std::fs::write(dir.join("source.png"), &png).unwrap();
+		let metadata = PngParser::new().parse_header_bytes(&png).unwrap();
+		write_ico(dir.join("png"), metadata, dir.join("source.png")).unwrap();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		write_ico_with_format(dir.join("bmp"), metadata, dir.join("source.png"), bmp).unwrap();
+
+		let entries = parse_ico(dir.join("png.ico")).unwrap();
+		assert_eq!(entries.len(), 1);
+		let entry = &entries[0];
+		assert_eq!((entry.width, entry.height, entry.color_planes, entry.bits_per_pixel), (48, 48, 1, 32));
+		assert_eq!((entry.offset, entry.storage, entry.hotspot), (22, EntryStorage::Png, None));
+		assert_eq!(entry.data, png);
+
+		let entries = parse_ico(dir.join("bmp.ico")).unwrap();
+		let entry = &entries[0];
+		assert_eq!((entry.width, entry.height, entry.bits_per_pixel), (48, 48, 32));
+		assert_eq!(entry.storage, EntryStorage::Bmp);
+		assert_eq!(decode_rgba(&entry.to_png().unwrap()).unwrap(), (48, 48, rgba));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reads_256px_dimensions_from_zero_bytes() {
+		let entries = parse_ico_bytes(&solid_ico(256, [1, 2, 3, 4])).unwrap();
+		assert_eq!((entries[0].width, entries[0].height), (256, 256));
+	}
+
+	#[test]
+	fn lists_sizes_from_the_directory_alone() {
+		let dir = temp_dir("list-sizes");
+		let pngs = [16, 48, 256].map(|size| solid_ico(size, [9, 9, 9, 255])[22..].to_vec());
+		let ico = encode_ico_from_bytes(&[&pngs[0][..], &pngs[1], &pngs[2]], EntryFormat::Png).unwrap();
+		let bmp = encode_ico_from_bytes(&[&pngs[0][..]], EntryFormat::Bmp(BmpOptions::default())).unwrap();
+		// Cut off after the directory, so reading any payload would fail.
+		std::fs::write(dir.join("icon.ico"), &ico[..6 + 3 * 16]).unwrap();
+		std::fs::write(dir.join("bmp.ico"), &bmp[..6 + 16]).unwrap();
+		assert_eq!(list_ico_sizes(dir.join("icon.ico")).unwrap(), [(16, 16, 32), (48, 48, 32), (256, 256, 32)]);
+		assert!(parse_ico(dir.join("icon.ico")).is_err());
+		assert_eq!(list_ico_sizes(dir.join("bmp.ico")).unwrap(), [(16, 16, 32)]);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn rejects_entries_outside_the_file() {
+		let ico = solid_ico(16, [0; 4]);
+		assert!(matches!(parse_ico_bytes(&ico[..4]), Err(PngIcoError::InvalidIco(_))));
+		assert!(matches!(parse_ico_bytes(&ico[..20]), Err(PngIcoError::InvalidIco(_))));
+		assert!(matches!(parse_ico_bytes(&ico[..ico.len() - 1]), Err(PngIcoError::EntryOutOfBounds(0, 22, ..))));
+	}
+
+	#[test]
+	fn reports_which_entry_is_out_of_bounds() {
+		let pngs = [16, 32].map(|size| solid_ico(size, [5; 4])[22..].to_vec());
+		let ico = encode_ico_from_bytes(&[&pngs[0][..], &pngs[1]], EntryFormat::Png).unwrap();
+		let with_entry = |field: usize, value: u32| {
+			let mut ico = ico.clone();
+			ico[6 + 16 + field..][..4].copy_from_slice(&value.to_le_bytes());
+			ico
+		};
+		let len = ico.len();
+		let past_eof = with_entry(12, len as u32 + 10);
+		match parse_ico_bytes(&past_eof) {
+			Err(PngIcoError::EntryOutOfBounds(index, offset, _, file_len)) => {
+				assert_eq!((index, offset as usize, file_len), (1, len + 10, len));
+			}
+			other => panic!("expected EntryOutOfBounds, got {:?}", other.map(|entries| entries.len())),
+		}
+		let overrun = with_entry(8, u32::MAX);
+		assert!(matches!(parse_ico_bytes(&overrun), Err(PngIcoError::EntryOutOfBounds(1, _, u32::MAX, _))));
+
+		let dir = temp_dir("entry-out-of-bounds");
+		std::fs::write(dir.join("overrun.ico"), &overrun).unwrap();
+		assert!(matches!(parse_ico(dir.join("overrun.ico")), Err(PngIcoError::EntryOutOfBounds(1, ..))));
+		let entry = get_entry_png(dir.join("overrun.ico"), 32, 32);
+		assert!(matches!(entry, Err(PngIcoError::EntryOutOfBounds(1, ..))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reads_png_entries_as_they_are_stored() {
+		let dir = temp_dir("get-entry-png");
+		let large = solid_ico(256, [10, 20, 30, 255])[22..].to_vec();
+		std::fs::write(dir.join("large.png"), &large).unwrap();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let builder = ConversionBuilder::new().size_formats(&[(32, bmp)]).embed_source(true);
+		builder.convert(dir.join("large.png"), dir.join("both.ico")).unwrap();
+
+		let png = get_entry_png(dir.join("both.ico"), 256, 256).unwrap().unwrap();
+		assert_eq!(png, large);
+		assert_eq!(PngParser::new().parse_header_bytes(&png).unwrap().width, 256);
+		// The 32px entry is a BMP, and there is no 48px entry.
+		assert_eq!(get_entry_png(dir.join("both.ico"), 32, 32).unwrap(), None);
+		assert_eq!(get_entry_png(dir.join("both.ico"), 48, 48).unwrap(), None);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn verifies_written_icons() {
+		let dir = temp_dir("verify-ico");
+		let png = solid_ico(16, [1, 2, 3, 255])[22..].to_vec();
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		std::fs::write(dir.join("good.ico"), encode_ico_from_bytes(&[&png, &png], bmp).unwrap()).unwrap();
+		verify_ico(dir.join("good.ico")).unwrap();
+		verify_ico_bytes(&solid_ico(256, [0; 4])).unwrap();
+		verify_ico_bytes(&crate::png::encode_cur_from_bytes(&png, (1, 1)).unwrap()).unwrap();
+		assert!(matches!(verify_ico(dir.join("missing.ico")), Err(PngIcoError::UnreadableFile(_))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reports_where_a_corrupt_icon_goes_wrong() {
+		let good = solid_ico(16, [1, 2, 3, 255]);
+		let corrupt = |at: usize, bytes: &[u8]| {
+			let mut ico = good.clone();
+			ico[at..at + bytes.len()].copy_from_slice(bytes);
+			match verify_ico_bytes(&ico) {
+				Err(PngIcoError::InvalidIcoAt(offset, _)) => offset,
+				other => panic!("expected InvalidIcoAt, got {:?}", other),
+			}
+		};
+		assert_eq!(corrupt(0, &[1, 0]), 0);
+		assert_eq!(corrupt(2, &[3, 0]), 2);
+		assert_eq!(corrupt(4, &[0, 0]), 4);
+		// A second directory entry would cover the first entry's data.
+		assert_eq!(corrupt(4, &[2, 0]), 18);
+		assert_eq!(corrupt(4, &[0xFF, 0]), 4);
+		// An offset inside the directory, and an offset or size that runs past the end.
+		assert_eq!(corrupt(18, &[6, 0, 0, 0]), 18);
+		assert_eq!(corrupt(18, &[23, 0, 0, 0]), 14);
+		assert_eq!(corrupt(14, &[0xFF, 0xFF, 0, 0]), 14);
+		// A PNG whose IHDR is damaged, and a payload that is neither a PNG nor a DIB.
+		assert_eq!(corrupt(22 + 12, b"IHDX"), 22);
+		assert_eq!(corrupt(22, &[0; 8]), 22);
+		assert!(matches!(verify_ico_bytes(&good[..5]), Err(PngIcoError::InvalidIcoAt(0, _))));
+	}
+}
@@ -0,0 +1,809 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use super::chunk::png_len;
+#[cfg(feature = "std")]
+use super::chunk::png_len_from_reader;
+use super::encoder::{encode_png, encode_png_as, DEFAULT_COMPRESSION_LEVEL};
+use super::input::input_to_png;
+#[cfg(feature = "std")]
+use super::input::read_input_from;
+use super::{rgba_metadata, ColorType, PngMetadata, PngParser};
+use crate::bmp::{encode_icon_dib, encode_paletted_icon_dib};
+use crate::{PngIcoError, Result};
+
+/// The largest width and height an ICO or CUR entry can have.
+pub const MAX_ICON_DIMENSION: u32 = 256;
+
+const ICONDIR_SIZE: usize = 6;
+const ICONDIRENTRY_SIZE: usize = 16;
+#[cfg(feature = "std")]
+const STREAM_BUFFER_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BmpOptions {
+	/// Pixels with alpha below this value are marked transparent in the AND mask used by legacy renderers.
+	/// Defaults to 1, so only fully transparent pixels are masked.
+	pub mask_threshold: u8,
+	/// Multiplies the color channels of 32bpp entries by their alpha before storing them, for consumers that expect
+	/// premultiplied alpha. Disabled by default, since Windows expects straight alpha. Paletted entries ignore it.
+	pub premultiply: bool,
+}
+
+impl Default for BmpOptions {
+	fn default() -> Self {
+		BmpOptions {
+			mask_threshold: 1,
+			premultiply: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryFormat {
+	/// Embed the PNG itself. Supported since Windows Vista and the smallest option for large sizes.
+	#[default]
+	Png,
+	/// Store a 32bpp BMP DIB with an AND mask, for older consumers that don't understand PNG entries.
+	Bmp(BmpOptions),
+	/// Store a 4bpp DIB with the PNG's palette as its color table. The source must be an indexed PNG of at most
+	/// 16 colors, leaving a slot free for black if any pixel is masked.
+	Bmp4(BmpOptions),
+	/// Like [`Bmp4`](EntryFormat::Bmp4), but 8bpp with up to 256 colors.
+	Bmp8(BmpOptions),
+}
+
+// ICO and CUR files share a layout, differing in the image type and the meaning of two entry fields.
+pub(crate) enum ResourceKind {
+	// By convention icons have 0 or 1 color planes, and nearly every file uses 1.
+	Icon { color_planes: u16 },
+	Cursor { hotspot: (u16, u16) },
+}
+
+impl ResourceKind {
+	pub(crate) const ICON: ResourceKind = ResourceKind::Icon { color_planes: 1 };
+
+	fn image_type(&self) -> u16 {
+		match self {
+			ResourceKind::Icon { .. } => 1,
+			ResourceKind::Cursor { .. } => 2,
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub(crate) fn extension(&self) -> &'static str {
+		match self {
+			ResourceKind::Icon { .. } => "ico",
+			ResourceKind::Cursor { .. } => "cur",
+		}
+	}
+}
+
+fn write_icon_dir(buf: &mut Vec<u8>, kind: &ResourceKind, count: u16) {
+	buf.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+	buf.extend_from_slice(&kind.image_type().to_le_bytes()); // Image type (1 = ICO, 2 = CUR)
+	buf.extend_from_slice(&count.to_le_bytes()); // Image count
+}
+
+// `offset` is the absolute position of the entry's payload, measured from the start of the file.
+fn write_icon_dir_entry(
+	buf: &mut Vec<u8>,
+	kind: &ResourceKind,
+	png: &PngMetadata,
+	size: u32,
+	offset: u32,
+) -> Result<()> {
+	let bits_per_pixel = png.bits_per_pixel()? as u16;
+	// Only palettes below 8bpp have their size recorded; 0 stands for "no palette or 256 colors".
+	let indexed = png.color_type() == ColorType::Indexed.to_u8();
+	let color_count = if indexed && bits_per_pixel < 8 { 1 << bits_per_pixel } else { 0 };
+	buf.extend_from_slice(&[
+		encode_dimension(png.width())?,  // Width
+		encode_dimension(png.height())?, // Height
+		color_count,                     // Color count
+		0,                               // Reserved
+	]);
+	// Cursors store the hotspot where icons store the color planes and bits per pixel.
+	let (planes, bits_per_pixel) = match kind {
+		ResourceKind::Icon { color_planes } => (*color_planes, bits_per_pixel),
+		ResourceKind::Cursor { hotspot } => *hotspot,
+	};
+	buf.extend_from_slice(&planes.to_le_bytes()); // Color planes / hotspot X
+	buf.extend_from_slice(&bits_per_pixel.to_le_bytes()); // Bits per pixel / hotspot Y
+	buf.extend_from_slice(&size.to_le_bytes()); // Image size
+	buf.extend_from_slice(&offset.to_le_bytes()); // Image offset
+	Ok(())
+}
+
+// The width or height byte of a directory entry, which has to store MAX_ICON_DIMENSION, 256, as 0.
+pub(crate) fn encode_dimension(dimension: u32) -> Result<u8> {
+	match dimension {
+		MAX_ICON_DIMENSION => Ok(0),
+		1..=255 => Ok(dimension as u8),
+		0 => Err(PngIcoError::InvalidSize(dimension)),
+		_ => Err(PngIcoError::DimensionTooLarge(dimension)),
+	}
+}
+
+// A PNG that has been read from disk once, so the same bytes provide both the header and the entry payload.
+#[cfg(feature = "std")]
+pub(crate) struct PngSource {
+	// The header of the file as it was read, which differs from `metadata` if the PNG had to be re-encoded.
+	pub source: PngMetadata,
+	pub metadata: PngMetadata,
+	pub data: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl PngSource {
+	pub fn read(path: impl AsRef<Path>) -> Result<PngSource> {
+		let path = path.as_ref();
+		let file = File::open(path).map_err(PngIcoError::UnreadableFile)?;
+		PngSource::from_reader(file, path)
+	}
+
+	// Reads the file at `path` through `reader` in one pass, and takes both the header and the payload from the bytes.
+	pub fn from_reader(reader: impl Read, path: &Path) -> Result<PngSource> {
+		let data = read_input_from(reader, path)?;
+		let parser = PngParser::new();
+		let source = parser.parse_header_bytes(&data)?;
+		let (metadata, data) = embeddable_png(&parser, data)?;
+		Ok(PngSource { source, metadata, data })
+	}
+
+	fn entry(&self) -> (&PngMetadata, &[u8]) {
+		(&self.metadata, &self.data)
+	}
+}
+
+pub(crate) fn encode_entries(entries: &[(&PngMetadata, &[u8])]) -> Result<Vec<u8>> {
+	encode_resource(&ResourceKind::ICON, entries)
+}
+
+pub(crate) fn encode_resource(kind: &ResourceKind, entries: &[(&PngMetadata, &[u8])]) -> Result<Vec<u8>> {
+	let lens = entries.iter().map(|(png, payload)| (*png, payload.len())).collect::<Vec<_>>();
+	let mut buf = encode_directory(kind, &lens)?;
+	buf.reserve_exact(entries.iter().map(|(_, payload)| payload.len()).sum::<usize>());
+	for (_, payload) in entries {
+		buf.extend_from_slice(payload);
+	}
+	Ok(buf)
+}
+
+// Validates the entries and builds the ICONDIR and directory table, from each entry's header and payload length. The
+// payloads are expected to follow it in order.
+pub(crate) fn encode_directory(kind: &ResourceKind, entries: &[(&PngMetadata, usize)]) -> Result<Vec<u8>> {
+	if entries.len() > u16::MAX as usize {
+		return Err(PngIcoError::TooManyImages(entries.len()));
+	}
+	encode_counted_directory(kind, entries.len() as u16, entries)
+}
+
+/// The ICONDIR and directory table of an ICO, for callers that write the entry payloads themselves, in order, right
+/// after it. Each entry is given as its PNG header and payload length. `count` is the image count to record in the
+/// ICONDIR; a file whose count differs from its entries is corrupt, so it's an error for it not to match.
+pub fn encode_ico_directory(count: u16, entries: &[(&PngMetadata, usize)]) -> Result<Vec<u8>> {
+	encode_counted_directory(&ResourceKind::ICON, count, entries)
+}
+
+fn encode_counted_directory(kind: &ResourceKind, count: u16, entries: &[(&PngMetadata, usize)]) -> Result<Vec<u8>> {
+	if entries.is_empty() {
+		return Err(PngIcoError::NoImages);
+	}
+	if let ResourceKind::Icon { color_planes } = *kind {
+		if color_planes > 1 {
+			return Err(PngIcoError::InvalidColorPlanes(color_planes));
+		}
+	}
+	// Payloads start right after the full directory table.
+	let header_size = ICONDIR_SIZE + ICONDIRENTRY_SIZE * entries.len();
+	let mut offset = u32::try_from(header_size).map_err(|_| PngIcoError::IcoTooLarge)?;
+	let mut buf = Vec::with_capacity(header_size);
+	write_icon_dir(&mut buf, kind, count);
+	let mut written = 0;
+	for &(png, len) in entries {
+		let len = u32::try_from(len).map_err(|_| PngIcoError::IcoTooLarge)?;
+		write_icon_dir_entry(&mut buf, kind, png, len, offset)?;
+		// Every payload has to end within the 4 GiB that the offsets can address.
+		offset = offset.checked_add(len).ok_or(PngIcoError::IcoTooLarge)?;
+		written += 1;
+	}
+	// Checked after the fact, so the header can't drift from what was actually written.
+	if written != count as usize {
+		return Err(PngIcoError::EntryCountMismatch(count, written));
+	}
+	Ok(buf)
+}
+
+// ICO consumers expect 8 bits per sample, so 16-bit PNGs are re-encoded at 8 bits. Other PNGs are kept verbatim,
+// except for any bytes after IEND.
+pub(crate) fn embeddable_png(parser: &PngParser, mut data: Vec<u8>) -> Result<(PngMetadata, Vec<u8>)> {
+	let metadata = parser.parse_header_bytes(&data)?;
+	if metadata.bit_depth() != 16 {
+		data.truncate(png_len(&data));
+		return Ok((metadata, data));
+	}
+	reencode_png(parser, &data, DEFAULT_COMPRESSION_LEVEL)
+}
+
+// Decodes a PNG and encodes it again at compression `level`, with 8 bits per sample. Images that can't be stored
+// that way as they are, because they're indexed or use fewer bits per sample, become RGBA.
+pub(crate) fn reencode_png(parser: &PngParser, data: &[u8], level: u8) -> Result<(PngMetadata, Vec<u8>)> {
+	let decoded = parser.clone().strip_16(true).decode_bytes(data)?;
+	let (width, height) = (decoded.metadata.width(), decoded.metadata.height());
+	let (color, info) = (decoded.metadata.color()?, &decoded.info);
+	let png = if decoded.metadata.bit_depth() == 8 && color != ColorType::Indexed {
+		encode_png_as(&decoded.png_data, width, height, color, info.transparency.as_ref(), info.color_space, level)
+	} else {
+		encode_png(&decoded.to_rgba8(), width, height, info.color_space, level)
+	};
+	Ok((parser.parse_header_bytes(&png)?, png))
+}
+
+// Like `embeddable_png`, for a PNG whose header the caller parsed separately, which must describe these bytes.
+#[cfg(feature = "std")]
+pub(crate) fn checked_png(parser: &PngParser, png: &PngMetadata, data: Vec<u8>) -> Result<(PngMetadata, Vec<u8>)> {
+	if parser.parse_header_bytes(&data)? != *png {
+		return Err(PngIcoError::MetadataMismatch);
+	}
+	embeddable_png(parser, data)
+}
+
+// Turns a PNG into the payload for `format`, along with the header that describes the payload.
+pub(crate) fn encode_payload(
+	parser: &PngParser,
+	png: PngMetadata,
+	data: Vec<u8>,
+	format: EntryFormat,
+) -> Result<(PngMetadata, Vec<u8>)> {
+	#[cfg(feature = "log")]
+	if format != EntryFormat::Png && (png.width() == MAX_ICON_DIMENSION || png.height() == MAX_ICON_DIMENSION) {
+		log::warn!(
+			"a {}x{} entry is stored as a BMP, which many systems render poorly at 256px; EntryFormat::Png is \
+			 recommended for that size",
+			png.width(),
+			png.height()
+		);
+	}
+	match format {
+		EntryFormat::Png => Ok((png, data)),
+		EntryFormat::Bmp(options) => {
+			let rgba = parser.decode_bytes(&data)?.to_rgba8();
+			let dib = encode_icon_dib(&rgba, png.width(), png.height(), options.mask_threshold, options.premultiply);
+			// The DIB is always 32bpp RGBA, whatever the source PNG's format.
+			Ok((rgba_metadata(png.width(), png.height()), dib))
+		}
+		EntryFormat::Bmp4(options) | EntryFormat::Bmp8(options) => {
+			let bit_depth = if let EntryFormat::Bmp4(_) = format { 4 } else { 8 };
+			let image = parser
+				.decode_indexed_bytes(&data)?
+				.ok_or(PngIcoError::PalettedBmp("the source is not an indexed PNG"))?;
+			let dib = encode_paletted_icon_dib(
+				&image.indices,
+				png.width(),
+				png.height(),
+				&image.palette,
+				bit_depth as u16,
+				options.mask_threshold,
+			)?;
+			let metadata = PngMetadata {
+				bit_depth,
+				interlace_method: 0,
+				..image.metadata
+			};
+			Ok((metadata, dib))
+		}
+	}
+}
+
+/// Builds an ICO from complete PNG files held in memory, reading each entry's header from its own bytes.
+/// This is the conversion path available without the `std` feature. Entries may also be BMP files, JPEGs with the
+/// `jpeg` feature or GIFs with the `gif` feature.
+pub fn encode_ico_from_bytes(pngs: &[&[u8]], format: EntryFormat) -> Result<Vec<u8>> {
+	let parser = PngParser::new();
+	let payloads = pngs
+		.iter()
+		.map(|&data| {
+			let (png, data) = embeddable_png(&parser, input_to_png(data.to_vec())?)?;
+			encode_payload(&parser, png, data, format)
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let entries = payloads.iter().map(|(png, payload)| (png, &payload[..])).collect::<Vec<_>>();
+	encode_entries(&entries)
+}
+
+/// Builds a single-entry ICO from 8-bit RGBA pixels, row by row from the top, such as a renderer's framebuffer. The
+/// pixels are PNG-encoded, so the image can be at most 256x256.
+pub fn encode_ico_from_rgba(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+	encode_dimension(width)?;
+	encode_dimension(height)?;
+	if pixels.len() as u64 != width as u64 * height as u64 * 4 {
+		return Err(PngIcoError::InvalidImageData("the RGBA buffer doesn't match the image size"));
+	}
+	let png = encode_png(pixels, width, height, None, DEFAULT_COMPRESSION_LEVEL);
+	encode_entries(&[(&rgba_metadata(width, height), &png)])
+}
+
+/// Builds a single-image cursor from a PNG held in memory. `hotspot` is the click point in pixels from the top-left.
+pub fn encode_cur_from_bytes(png: &[u8], hotspot: (u16, u16)) -> Result<Vec<u8>> {
+	let (metadata, png) = embeddable_png(&PngParser::new(), png.to_vec())?;
+	encode_resource(&ResourceKind::Cursor { hotspot }, &[(&metadata, &png)])
+}
+
+#[cfg(feature = "std")]
+pub fn write_ico_multi_to<W: Write>(writer: &mut W, images: &[(PngMetadata, PathBuf)]) -> Result<()> {
+	writer
+		.write_all(&encode_ico_multi(images)?)
+		.map_err(PngIcoError::UnwritableFile)
+}
+
+/// Like [`write_ico_multi_to`], but copies each PNG from its file into `writer` through a small buffer instead of
+/// reading them all into memory first, so memory use stays flat however large the images are. The files are copied
+/// verbatim, so those `write_ico_multi_to` would have to change are rejected: 16-bit PNGs, which it re-encodes, and
+/// PNGs with data after IEND, which it trims. For the rest the output is the same byte for byte. The file lengths are
+/// read up front for the directory, so the files must not change meanwhile.
+#[cfg(feature = "std")]
+pub fn stream_ico_multi_to<W: Write>(writer: &mut W, images: &[(PngMetadata, PathBuf)]) -> Result<()> {
+	let parser = PngParser::new();
+	let mut files = images
+		.iter()
+		.map(|(png, path)| {
+			let mut file = File::open(path).map_err(PngIcoError::UnreadableFile)?;
+			let len = file.metadata().map_err(PngIcoError::UnreadableFile)?.len();
+			let source = parser.parse_header_from_reader(&mut file)?;
+			if source != *png {
+				return Err(PngIcoError::MetadataMismatch);
+			}
+			if source.bit_depth() == 16 {
+				return Err(PngIcoError::InvalidEntryBitDepth(source.bits_per_pixel()? as u8));
+			}
+			if png_len_from_reader(&mut file, len).map_err(PngIcoError::UnreadableFile)? != len {
+				return Err(PngIcoError::Nonconformant("data follows IEND"));
+			}
+			file.seek(SeekFrom::Start(0)).map_err(PngIcoError::UnreadableFile)?;
+			Ok((file, len as usize))
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let entries = images.iter().zip(&files).map(|((png, _), &(_, len))| (png, len)).collect::<Vec<_>>();
+	let directory = encode_directory(&ResourceKind::ICON, &entries)?;
+	writer.write_all(&directory).map_err(PngIcoError::UnwritableFile)?;
+	let mut buf = [0; STREAM_BUFFER_SIZE];
+	for (file, len) in &mut files {
+		let mut remaining = *len;
+		while remaining > 0 {
+			let read = match file.read(&mut buf[..remaining.min(STREAM_BUFFER_SIZE)]) {
+				Ok(0) => {
+					let err = io::Error::new(io::ErrorKind::UnexpectedEof, "the file got shorter while it was read");
+					return Err(PngIcoError::UnreadableFile(err));
+				}
+				Ok(read) => read,
+				Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+				Err(err) => return Err(PngIcoError::UnreadableFile(err)),
+			};
+			writer.write_all(&buf[..read]).map_err(PngIcoError::UnwritableFile)?;
+			remaining -= read;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(feature = "std")]
+pub fn write_ico_to<W: Write>(writer: &mut W, png: PngMetadata, png_path: impl AsRef<Path>) -> Result<()> {
+	write_ico_multi_to(writer, &[(png, PathBuf::from(png_path.as_ref()))])
+}
+
+#[cfg(feature = "std")]
+pub fn encode_ico_multi(images: &[(PngMetadata, PathBuf)]) -> Result<Vec<u8>> {
+	let parser = PngParser::new();
+	let payloads = images
+		.iter()
+		.map(|(png, path)| checked_png(&parser, png, std::fs::read(path).map_err(PngIcoError::UnreadableFile)?))
+		.collect::<Result<Vec<_>>>()?;
+	let entries = payloads.iter().map(|(png, payload)| (png, &payload[..])).collect::<Vec<_>>();
+	encode_entries(&entries)
+}
+
+#[cfg(feature = "std")]
+pub fn encode_ico(png: PngMetadata, png_path: impl AsRef<Path>) -> Result<Vec<u8>> {
+	encode_ico_multi(&[(png, PathBuf::from(png_path.as_ref()))])
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn write_output(out_path: impl AsRef<Path>, buf: &[u8]) -> Result<()> {
+	write_output_as(out_path, &ResourceKind::ICON, buf)
+}
+
+#[cfg(feature = "std")]
+fn write_output_as(out_path: impl AsRef<Path>, kind: &ResourceKind, buf: &[u8]) -> Result<()> {
+	write_file(output_path(out_path, kind), buf)
+}
+
+// `out_path` with the extension of the kind of file being written.
+#[cfg(feature = "std")]
+pub(crate) fn output_path(out_path: impl AsRef<Path>, kind: &ResourceKind) -> PathBuf {
+	let mut out_path = out_path.as_ref().to_path_buf();
+	out_path.set_extension(kind.extension());
+	out_path
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn write_file(path: impl AsRef<Path>, buf: &[u8]) -> Result<()> {
+	std::fs::write(path, buf).map_err(PngIcoError::UnwritableFile)
+}
+
+#[cfg(feature = "std")]
+pub fn write_ico_multi(out_path: impl AsRef<Path>, images: &[(PngMetadata, PathBuf)]) -> Result<()> {
+	let buf = encode_ico_multi(images)?;
+	write_output(out_path, &buf)
+}
+
+/// Writes a single-entry ICO for the PNG at `png_path`. The extension of `out_path` is replaced with .ico;
+/// [`ConversionBuilder::exact_output_path`](super::ConversionBuilder::exact_output_path) keeps it.
+#[cfg(feature = "std")]
+pub fn write_ico(out_path: impl AsRef<Path>, png: PngMetadata, png_path: impl AsRef<Path>) -> Result<()> {
+	let buf = encode_ico(png, png_path)?;
+	write_output(out_path, &buf)
+}
+
+/// Like [`write_ico`], but lets the entry be stored in a format other than PNG.
+#[cfg(feature = "std")]
+pub fn write_ico_with_format(
+	out_path: impl AsRef<Path>,
+	png: PngMetadata,
+	png_path: impl AsRef<Path>,
+	format: EntryFormat,
+) -> Result<()> {
+	let parser = PngParser::new();
+	let data = std::fs::read(png_path.as_ref()).map_err(PngIcoError::UnreadableFile)?;
+	let (png, data) = checked_png(&parser, &png, data)?;
+	let (png, payload) = encode_payload(&parser, png, data, format)?;
+	let buf = encode_entries(&[(&png, &payload)])?;
+	write_output(out_path, &buf)
+}
+
+/// Writes a single-image cursor. `hotspot` is the click point in pixels from the image's top-left corner.
+#[cfg(feature = "std")]
+pub fn write_cur(
+	out_path: impl AsRef<Path>,
+	png: PngMetadata,
+	png_path: impl AsRef<Path>,
+	hotspot: (u16, u16),
+) -> Result<()> {
+	let data = std::fs::read(png_path.as_ref()).map_err(PngIcoError::UnreadableFile)?;
+	let (png, payload) = checked_png(&PngParser::new(), &png, data)?;
+	let kind = ResourceKind::Cursor { hotspot };
+	let buf = encode_resource(&kind, &[(&png, &payload)])?;
+	write_output_as(out_path, &kind, &buf)
+}
+
+/// Converts a PNG of at most 256x256 into a single-entry ICO, embedding the PNG verbatim. Bytes after its IEND chunk,
+/// which aren't part of the image, are left out.
+/// 16-bit PNGs are the exception and are re-encoded with 8 bits per sample.
+/// Larger images are rejected; see [`png_to_ico_scaled`](super::png_to_ico_scaled) to downscale them instead.
+#[cfg(feature = "std")]
+pub fn png_to_ico(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+	convert_reporting(input, output).map(|_| ())
+}
+
+/// Like [`png_to_ico`], but also returns the source's header, parsed from the bytes read for the conversion, for
+/// logging or a manifest without reading the file again. A 16-bit source reports its own header, not that of the
+/// re-encoded entry. Other formats report the header of the PNG they were converted to.
+#[cfg(feature = "std")]
+pub fn convert_reporting(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<PngMetadata> {
+	let source = PngSource::read(input)?;
+	let buf = encode_entries(&[source.entry()])?;
+	write_output(output, &buf)?;
+	Ok(source.source)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+
+	use super::*;
+	#[cfg(feature = "log")]
+	use crate::png::test_support::captured_warnings;
+	use crate::png::test_support::{png_with_chunks, rgba_png, temp_dir};
+	use crate::png::testing::{decode_rgba, sample_pngs};
+	use crate::png::{parse_ico_bytes, IcoEntry};
+
+	fn header(png: &[u8]) -> PngMetadata {
+		PngParser::new().parse_header_bytes(png).unwrap()
+	}
+
+	fn only_entry(ico: &[u8]) -> IcoEntry {
+		let mut entries = parse_ico_bytes(ico).unwrap();
+		assert_eq!(entries.len(), 1);
+		entries.remove(0)
+	}
+
+	fn gradient_png(width: u32, height: u32) -> Vec<u8> {
+		rgba_png(width, height, |x, y| [x as u8, y as u8, 128, 255])
+	}
+
+	// The RGBA pixels of a PNG, to compare images without comparing their encoding.
+	fn decoded(png: &[u8]) -> Vec<u8> {
+		PngParser::new().decode_bytes(png).unwrap().to_rgba8()
+	}
+
+	#[test]
+	fn encodes_256_as_0_in_directory_entries() {
+		assert_eq!(encode_dimension(1).unwrap(), 1);
+		assert_eq!(encode_dimension(255).unwrap(), 255);
+		assert_eq!(encode_dimension(256).unwrap(), 0);
+		assert!(matches!(encode_dimension(257), Err(PngIcoError::DimensionTooLarge(257))));
+		assert!(matches!(encode_dimension(0), Err(PngIcoError::InvalidSize(0))));
+	}
+
+	#[test]
+	fn wraps_rgba_buffers_in_an_ico() {
+		let rgba = (0..40 * 40).flat_map(|i| [(i % 40) as u8 * 6, (i / 40) as u8 * 6, 0, 255]).collect::<Vec<_>>();
+		let entry = only_entry(&encode_ico_from_rgba(&rgba, 40, 40).unwrap());
+		assert_eq!((entry.width, entry.height, entry.bits_per_pixel), (40, 40, 32));
+		assert_eq!(decode_rgba(&entry.data).unwrap(), (40, 40, rgba.clone()));
+		let err = encode_ico_from_rgba(&rgba[4..], 40, 40).unwrap_err();
+		assert!(matches!(err, PngIcoError::InvalidImageData(_)));
+	}
+
+	#[test]
+	fn entry_offsets_point_at_their_payloads() {
+		let pngs = [gradient_png(16, 16), gradient_png(32, 32), gradient_png(48, 48)];
+		let ico = encode_ico_from_bytes(&pngs.each_ref().map(|png| &png[..]), EntryFormat::Png).unwrap();
+		let mut expected_offset = ICONDIR_SIZE + pngs.len() * ICONDIRENTRY_SIZE;
+		for (index, png) in pngs.iter().enumerate() {
+			let entry = &ico[ICONDIR_SIZE + index * ICONDIRENTRY_SIZE..][..ICONDIRENTRY_SIZE];
+			let size = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as usize;
+			let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as usize;
+			assert_eq!((offset, size), (expected_offset, png.len()), "entry {}", index);
+			assert_eq!(&ico[offset..offset + size], &png[..]);
+			expected_offset += size;
+		}
+		assert_eq!(expected_offset, ico.len());
+	}
+
+	#[test]
+	fn reads_the_source_only_once() {
+		// Counts the bytes handed out, so a second pass over the file would add up to more than its length.
+		struct Counting<R> {
+			inner: R,
+			read: usize,
+		}
+		impl<R: Read> Read for Counting<R> {
+			fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+				let read = self.inner.read(buf)?;
+				self.read += read;
+				Ok(read)
+			}
+		}
+
+		let png = gradient_png(16, 16);
+		let mut reader = Counting { inner: io::Cursor::new(&png), read: 0 };
+		let source = PngSource::from_reader(&mut reader, Path::new("source.png")).unwrap();
+		assert_eq!(reader.read, png.len());
+		assert_eq!((source.source, source.metadata), (header(&png), header(&png)));
+		assert_eq!(source.data, png);
+	}
+
+	#[test]
+	fn writes_to_any_writer_as_to_disk() {
+		let dir = temp_dir("write-to");
+		let png = gradient_png(24, 24);
+		let path = dir.join("source.png");
+		std::fs::write(&path, &png).unwrap();
+		write_ico(dir.join("on-disk"), header(&png), &path).unwrap();
+		let on_disk = std::fs::read(dir.join("on-disk.ico")).unwrap();
+
+		let mut cursor = io::Cursor::new(Vec::new());
+		write_ico_to(&mut cursor, header(&png), &path).unwrap();
+		assert_eq!(cursor.into_inner(), on_disk);
+		let mut streamed = Vec::new();
+		stream_ico_multi_to(&mut streamed, &[(header(&png), path.clone())]).unwrap();
+		assert_eq!(streamed, on_disk);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn streams_several_entries_as_they_are_buffered() {
+		let dir = temp_dir("stream-multi");
+		let images = [16, 48, 256]
+			.iter()
+			.map(|&size| {
+				let png = gradient_png(size, size);
+				let path = dir.join(format!("{}.png", size));
+				std::fs::write(&path, &png).unwrap();
+				(header(&png), path)
+			})
+			.collect::<Vec<_>>();
+		let mut streamed = Vec::new();
+		stream_ico_multi_to(&mut streamed, &images).unwrap();
+		assert_eq!(streamed, encode_ico_multi(&images).unwrap());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn rejects_payloads_past_what_the_offsets_can_address() {
+		let png = header(&gradient_png(16, 16));
+		let too_long = encode_ico_directory(1, &[(&png, u32::MAX as usize + 1)]);
+		assert!(matches!(too_long, Err(PngIcoError::IcoTooLarge)));
+		let past_the_end = encode_ico_directory(2, &[(&png, u32::MAX as usize - 100), (&png, 100)]);
+		assert!(matches!(past_the_end, Err(PngIcoError::IcoTooLarge)));
+		assert!(encode_ico_directory(2, &[(&png, u32::MAX as usize - 200), (&png, 100)]).is_ok());
+	}
+
+	#[test]
+	fn streams_only_pngs_it_can_copy_verbatim() {
+		let dir = temp_dir("stream-rejects");
+		let png = gradient_png(16, 16);
+		let path = dir.join("source.png");
+		std::fs::write(&path, &png).unwrap();
+		let other = header(&gradient_png(32, 32));
+		let mismatch = stream_ico_multi_to(&mut Vec::new(), &[(other, path.clone())]);
+		assert!(matches!(mismatch, Err(PngIcoError::MetadataMismatch)));
+
+		let deep = png_with_chunks((1, 1), 16, ColorType::TruecolorAlpha, &[], &[0; 8]);
+		std::fs::write(&path, &deep).unwrap();
+		let deep = stream_ico_multi_to(&mut Vec::new(), &[(header(&deep), path.clone())]);
+		assert!(matches!(deep, Err(PngIcoError::InvalidEntryBitDepth(64))));
+
+		std::fs::write(&path, [&png[..], b"PK\x03\x04"].concat()).unwrap();
+		let mut out = Vec::new();
+		let trailing = stream_ico_multi_to(&mut out, &[(header(&png), path.clone())]);
+		assert!(matches!(trailing, Err(PngIcoError::Nonconformant("data follows IEND"))));
+		assert!(out.is_empty());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reports_the_header_of_the_converted_source() {
+		let dir = temp_dir("convert-reporting");
+		for (name, png) in sample_pngs() {
+			let input = dir.join("source.png");
+			std::fs::write(&input, &png).unwrap();
+			let metadata = convert_reporting(&input, dir.join("out.ico")).unwrap();
+			assert_eq!(metadata, PngParser::new().parse_header(&input).unwrap(), "{}", name);
+			assert_eq!(only_entry(&std::fs::read(dir.join("out.ico")).unwrap()).width, metadata.width, "{}", name);
+		}
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn leaves_bytes_after_iend_out_of_the_entry() {
+		let dir = temp_dir("trailing-bytes");
+		let png = gradient_png(16, 16);
+		let polyglot = [&png[..], b"PK\x03\x04 an appended archive"].concat();
+		std::fs::write(dir.join("source.png"), &polyglot).unwrap();
+		png_to_ico(dir.join("source.png"), dir.join("out.ico")).unwrap();
+		assert_eq!(only_entry(&std::fs::read(dir.join("out.ico")).unwrap()).data, png);
+		assert_eq!(only_entry(&encode_ico_from_bytes(&[&polyglot], EntryFormat::Png).unwrap()).data, png);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn reports_writer_errors() {
+		struct Full;
+		impl Write for Full {
+			fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+				Err(io::Error::new(io::ErrorKind::WriteZero, "no space left"))
+			}
+
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+
+		let dir = temp_dir("write-to-full");
+		let png = gradient_png(8, 8);
+		std::fs::write(dir.join("source.png"), &png).unwrap();
+		let images = [(header(&png), dir.join("source.png"))];
+		assert!(matches!(write_ico_multi_to(&mut Full, &images), Err(PngIcoError::UnwritableFile(_))));
+		assert!(matches!(stream_ico_multi_to(&mut Full, &images), Err(PngIcoError::UnwritableFile(_))));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn writes_cursors_with_their_hotspot() {
+		let dir = temp_dir("write-cur");
+		let png = gradient_png(32, 32);
+		let mut padded = png.clone();
+		padded.extend_from_slice(b"trailing bytes");
+		std::fs::write(dir.join("cursor.png"), &padded).unwrap();
+		write_cur(dir.join("cursor"), header(&png), dir.join("cursor.png"), (5, 17)).unwrap();
+
+		let cur = std::fs::read(dir.join("cursor.cur")).unwrap();
+		assert_eq!(cur[2..4], 2u16.to_le_bytes());
+		let entry = only_entry(&cur);
+		assert_eq!(entry.hotspot, Some((5, 17)));
+		assert_eq!(entry.to_png().unwrap(), png);
+		assert_eq!(cur, encode_cur_from_bytes(&padded, (5, 17)).unwrap());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn re_encodes_16_bit_sources_for_every_format() {
+		let dir = temp_dir("write-16-bit");
+		let samples = (0..2 * 2 * 8).map(|i| (i * 37) as u8).collect::<Vec<_>>();
+		let png = png_with_chunks((2, 2), 16, ColorType::TruecolorAlpha, &[], &samples);
+		let path = dir.join("deep.png");
+		std::fs::write(&path, &png).unwrap();
+
+		write_ico_with_format(dir.join("deep"), header(&png), &path, EntryFormat::Png).unwrap();
+		let entry = only_entry(&std::fs::read(dir.join("deep.ico")).unwrap());
+		assert_eq!(entry.bits_per_pixel, 32);
+		assert_eq!(header(&entry.to_png().unwrap()).bit_depth(), 8);
+
+		write_cur(dir.join("deep"), header(&png), &path, (0, 0)).unwrap();
+		let entry = only_entry(&std::fs::read(dir.join("deep.cur")).unwrap());
+		assert_eq!(header(&entry.to_png().unwrap()).bit_depth(), 8);
+
+		let ico = encode_ico_multi(&[(header(&png), path)]).unwrap();
+		assert_eq!(header(&only_entry(&ico).to_png().unwrap()).bit_depth(), 8);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn rejects_headers_of_other_files() {
+		let dir = temp_dir("write-mismatch");
+		std::fs::write(dir.join("small.png"), gradient_png(16, 16)).unwrap();
+		let other = || header(&gradient_png(32, 32));
+		let path = dir.join("small.png");
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let bmp = write_ico_with_format(&path, other(), &path, bmp);
+		assert!(matches!(bmp, Err(PngIcoError::MetadataMismatch)));
+		let cur = write_cur(&path, other(), &path, (0, 0));
+		assert!(matches!(cur, Err(PngIcoError::MetadataMismatch)));
+		assert!(matches!(encode_ico(other(), &path), Err(PngIcoError::MetadataMismatch)));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn stores_indexed_sources_as_paletted_dibs() {
+		let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+		let chunks: &[(&[u8; 4], &[u8])] = &[(b"PLTE", &palette)];
+		let png = png_with_chunks((3, 2), 4, ColorType::Indexed, chunks, &[0x01, 0x20, 0x32, 0x10]);
+		let options = BmpOptions::default();
+		for (format, bits_per_pixel) in [(EntryFormat::Bmp4(options), 4), (EntryFormat::Bmp8(options), 8)] {
+			let entry = only_entry(&encode_ico_from_bytes(&[&png], format).unwrap());
+			assert_eq!((entry.bits_per_pixel, entry.storage), (bits_per_pixel, crate::png::EntryStorage::Bmp));
+			assert_eq!(decoded(&entry.to_png().unwrap()), decoded(&png));
+		}
+		let rgba = gradient_png(4, 4);
+		let err = encode_ico_from_bytes(&[&rgba], EntryFormat::Bmp4(options)).unwrap_err();
+		assert!(matches!(err, PngIcoError::PalettedBmp(_)));
+	}
+
+	#[cfg(feature = "log")]
+	#[test]
+	fn warns_about_256px_bmp_entries() {
+		let large = gradient_png(256, 256);
+		let bmp = EntryFormat::Bmp(BmpOptions::default());
+		let warnings = captured_warnings(|| {
+			encode_ico_from_bytes(&[&large], bmp).unwrap();
+		});
+		assert_eq!(warnings.len(), 1);
+		assert!(warnings[0].contains("256x256"), "{}", warnings[0]);
+
+		let small = gradient_png(32, 32);
+		assert!(captured_warnings(|| {
+			encode_ico_from_bytes(&[&large], EntryFormat::Png).unwrap();
+			encode_ico_from_bytes(&[&small], bmp).unwrap();
+		})
+		.is_empty());
+	}
+
+	#[test]
+	fn rejects_directory_counts_that_differ_from_the_entries() {
+		let png = gradient_png(16, 16);
+		let entries = [(&header(&png), png.len()), (&header(&png), png.len())];
+		assert!(matches!(encode_ico_directory(3, &entries), Err(PngIcoError::EntryCountMismatch(3, 2))));
+		assert!(matches!(encode_ico_directory(1, &entries), Err(PngIcoError::EntryCountMismatch(1, 2))));
+		let directory = encode_ico_directory(2, &entries).unwrap();
+		assert_eq!(directory.len(), ICONDIR_SIZE + 2 * ICONDIRENTRY_SIZE);
+		assert_eq!(directory[4..6], 2u16.to_le_bytes());
+	}
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use super::ConversionBuilder;
+use crate::resize::{resize_with, ResizeFilter};
+use crate::Result;
+
+/// The sizes that icons usually come in, from the small ones in menus and title bars to the large ones in Explorer.
+pub const STANDARD_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+/// The [`STANDARD_SIZES`] that a `width` by `height` source can fill without being upscaled, which blurs it.
+/// Empty for sources smaller than 16 pixels.
+pub fn suggested_sizes(width: u32, height: u32) -> Vec<u32> {
+	STANDARD_SIZES.iter().copied().filter(|&size| size <= width.min(height)).collect()
+}
+
+// The largest dimensions that fit within a `max` square while keeping the aspect ratio.
+pub(crate) fn fit_within(width: u32, height: u32, max: u32) -> (u32, u32) {
+	let longest = width.max(height) as u64;
+	let scale = |side: u32| ((side as u64 * max as u64 + longest / 2) / longest).max(1) as u32;
+	(scale(width), scale(height))
+}
+
+// Scales the image to fit a `size` square, keeping its aspect ratio, and centers it on a transparent canvas.
+pub(crate) fn fit_to_square(
+	rgba: &[u8],
+	width: u32,
+	height: u32,
+	size: u32,
+	filter: ResizeFilter,
+	linear_light: bool,
+) -> Result<Vec<u8>> {
+	let (fit_w, fit_h) = fit_within(width, height, size);
+	let scaled = resize_with(rgba, width, height, fit_w, fit_h, filter, linear_light)?;
+	Ok(center_on_canvas(scaled, fit_w, fit_h, size))
+}
+
+// Centers an image that fits within a `size` square on a transparent canvas of that size.
+pub(crate) fn center_on_canvas(rgba: Vec<u8>, width: u32, height: u32, size: u32) -> Vec<u8> {
+	if width == size && height == size {
+		return rgba;
+	}
+
+	let mut canvas = vec![0u8; size as usize * size as usize * 4];
+	let (left, top) = ((size - width) as usize / 2, (size - height) as usize / 2);
+	for (y, row) in rgba.chunks(width as usize * 4).enumerate() {
+		let start = ((top + y) * size as usize + left) * 4;
+		canvas[start..start + row.len()].copy_from_slice(row);
+	}
+	canvas
+}
+
+/// What to do with a source image whose width and height differ, since icons are square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonSquarePolicy {
+	/// Reject the image with [`PngIcoError::NotSquare`].
+	Error,
+	/// Embed the image as it is, with a warning through the `log` crate if the `log` feature is enabled.
+	#[default]
+	Warn,
+	/// Center the image on a transparent square canvas as large as its longer side.
+	PadToSquare,
+}
+
+/// Like [`png_to_ico`](super::png_to_ico), but applies `policy` when the source image isn't square.
+pub fn png_to_ico_with_policy(input: impl AsRef<Path>, output: impl AsRef<Path>, policy: NonSquarePolicy) -> Result<()> {
+	ConversionBuilder::new().non_square(policy).convert(input, output)
+}
+
+pub fn generate_icon_set(source_png: impl AsRef<Path>, sizes: &[u32], out_path: impl AsRef<Path>) -> Result<()> {
+	ConversionBuilder::new().sizes(sizes).convert(source_png, out_path)
+}
+
+/// Like [`generate_icon_set`], but the source is also embedded as it is, ahead of the `sizes` built from it. See
+/// [`ConversionBuilder::embed_source`].
+pub fn generate_icon_set_with_source(
+	source_png: impl AsRef<Path>,
+	sizes: &[u32],
+	out_path: impl AsRef<Path>,
+) -> Result<()> {
+	ConversionBuilder::new().sizes(sizes).embed_source(true).convert(source_png, out_path)
+}
+
+/// Like [`generate_icon_set`], but with the bits per pixel of each size, as described at
+/// [`ConversionBuilder::bit_depths`].
+pub fn generate_icon_set_with_depths(
+	source_png: impl AsRef<Path>,
+	sizes: &[(u32, u8)],
+	out_path: impl AsRef<Path>,
+) -> Result<()> {
+	let only_sizes = sizes.iter().map(|&(size, _)| size).collect::<Vec<_>>();
+	ConversionBuilder::new().sizes(&only_sizes).bit_depths(sizes).convert(source_png, out_path)
+}
+
+/// Like [`png_to_ico`](super::png_to_ico), but a PNG larger than 256x256 is downscaled to fit, keeping its aspect
+/// ratio, and re-encoded instead of being rejected. Images that already fit are embedded verbatim.
+///
+/// `png_to_ico` stays the default and strict path, which errors on oversized images.
+pub fn png_to_ico_scaled(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+	ConversionBuilder::new().auto_scale(true).convert(input, output)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::parse_ico;
+	use crate::png::test_support::{rgba_png, temp_dir};
+
+	#[test]
+	fn suggests_only_sizes_that_need_no_upscaling() {
+		assert_eq!(suggested_sizes(70, 70), [16, 24, 32, 48, 64]);
+		assert_eq!(suggested_sizes(300, 40), [16, 24, 32]);
+		assert_eq!(suggested_sizes(256, 256), STANDARD_SIZES);
+		assert!(suggested_sizes(15, 15).is_empty());
+	}
+
+	#[test]
+	fn auto_sizes_builds_the_suggested_entries() {
+		let dir = temp_dir("auto-sizes");
+		let input = dir.join("source.png");
+		std::fs::write(&input, rgba_png(70, 70, |x, y| [x as u8, y as u8, 0, 255])).unwrap();
+		ConversionBuilder::new().auto_sizes().convert(&input, dir.join("out.ico")).unwrap();
+		let widths = parse_ico(dir.join("out.ico")).unwrap().iter().map(|entry| entry.width).collect::<Vec<_>>();
+		assert_eq!(widths, [16, 24, 32, 48, 64]);
+	}
+
+	#[test]
+	fn stores_each_size_at_its_bit_depth() {
+		let dir = temp_dir("icon-set-depths");
+		let input = dir.join("source.png");
+		std::fs::write(&input, rgba_png(256, 256, |x, y| [x as u8, y as u8, 128, 255])).unwrap();
+		let depths = [(16, 4), (32, 8), (256, 32)];
+		generate_icon_set_with_depths(&input, &depths, dir.join("out.ico")).unwrap();
+		let entries = parse_ico(dir.join("out.ico")).unwrap();
+		let stored = entries.iter().map(|entry| (entry.width, entry.bits_per_pixel)).collect::<Vec<_>>();
+		assert_eq!(stored, [(16, 4), (32, 8), (256, 32)]);
+		assert!(matches!(
+			generate_icon_set_with_depths(&input, &[(16, 24)], dir.join("bad.ico")),
+			Err(crate::PngIcoError::InvalidEntryBitDepth(24))
+		));
+	}
+
+	#[test]
+	fn downscales_in_linear_light_unless_told_not_to() {
+		let dir = temp_dir("icon-set-linear");
+		let input = dir.join("checkerboard.png");
+		let white = |x: u32, y: u32| (x + y) % 2 == 1;
+		std::fs::write(&input, rgba_png(64, 64, |x, y| if white(x, y) { [255; 4] } else { [0, 0, 0, 255] })).unwrap();
+		let gray = |builder: ConversionBuilder| {
+			builder.sizes(&[16]).convert(&input, dir.join("out.ico")).unwrap();
+			let entry = parse_ico(dir.join("out.ico")).unwrap().remove(0);
+			crate::png::testing::decode_rgba(&entry.data).unwrap().2[0]
+		};
+		// Half the light of white is about 188 in sRGB, while averaging the sRGB values gives the darker 128.
+		assert!((187..=188).contains(&gray(ConversionBuilder::new())));
+		assert!((127..=128).contains(&gray(ConversionBuilder::new().linear_light(false))));
+	}
+}
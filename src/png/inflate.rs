@@ -0,0 +1,55 @@
+use alloc::vec::Vec;
+
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+use crate::{PngIcoError, Result};
+
+// Decompresses a zlib stream (RFC 1950) wrapping raw DEFLATE data (RFC 1951), failing as soon as it inflates past
+// `limit` bytes rather than growing the output without bound.
+pub(crate) fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+	decompress_to_vec_zlib_with_limit(data, limit).map_err(|error| {
+		PngIcoError::Decompression(match error.status {
+			TINFLStatus::HasMoreOutput => "data inflates past its expected size",
+			TINFLStatus::Adler32Mismatch => "Adler-32 checksum mismatch",
+			TINFLStatus::FailedCannotMakeProgress => "compressed data ends early",
+			_ => "invalid compressed data",
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// "hello", as compressed by zlib at its default level.
+	const HELLO: [u8; 13] = [0x78, 0x9C, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x07, 0x00, 0x06, 0x2C, 0x02, 0x15];
+
+	#[test]
+	fn decompresses_zlib_streams() {
+		assert_eq!(zlib_decompress(&HELLO, 5).unwrap(), b"hello");
+	}
+
+	#[test]
+	fn rejects_a_wrong_checksum() {
+		let mut data = HELLO;
+		data[12] ^= 1;
+		assert!(matches!(zlib_decompress(&data, 5), Err(PngIcoError::Decompression("Adler-32 checksum mismatch"))));
+	}
+
+	#[test]
+	fn rejects_truncated_streams() {
+		for len in 0..HELLO.len() {
+			assert!(
+				matches!(zlib_decompress(&HELLO[..len], 5), Err(PngIcoError::Decompression(_))),
+				"{} bytes",
+				len
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_streams_that_inflate_past_the_limit() {
+		let error = zlib_decompress(&HELLO, 4);
+		assert!(matches!(error, Err(PngIcoError::Decompression("data inflates past its expected size"))));
+	}
+}
@@ -0,0 +1,176 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use super::encoder::{encode_png, DEFAULT_COMPRESSION_LEVEL};
+use super::is_png;
+use crate::bmp::{decode_bmp, BMP_SIGNATURE};
+#[cfg(feature = "gif")]
+use crate::gif::decode_gif;
+#[cfg(feature = "jpeg")]
+use crate::jpeg::decode_jpeg;
+#[cfg(feature = "tga")]
+use crate::tga::decode_tga;
+use crate::{PngIcoError, Result};
+
+// A JPEG's SOI marker, immediately followed by the next marker.
+const JPEG_SIGNATURE: [u8; 3] = [0xFF, 0xD8, 0xFF];
+const GIF_SIGNATURES: [&[u8; 6]; 2] = [b"GIF87a", b"GIF89a"];
+// TGA 2.0 files end with this, after the offsets of their optional extension area and developer directory.
+const TGA_FOOTER_SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+
+/// An image format recognized by [`detect_format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputFormat {
+	Png,
+	/// Converted with the `jpeg` feature.
+	Jpeg,
+	Bmp,
+	/// Converted with the `gif` feature.
+	Gif,
+	/// Converted with the `tga` feature.
+	Tga,
+}
+
+impl InputFormat {
+	pub fn name(self) -> &'static str {
+		match self {
+			InputFormat::Png => "PNG",
+			InputFormat::Jpeg => "JPEG",
+			InputFormat::Bmp => "BMP",
+			InputFormat::Gif => "GIF",
+			InputFormat::Tga => "TGA",
+		}
+	}
+}
+
+/// Identifies the format of an image file from its first bytes, whatever the file is called. TGAs have no signature
+/// at the start, so they're recognized by the footer of TGA 2.0 files instead; older ones aren't recognized.
+pub fn detect_format(data: &[u8]) -> Option<InputFormat> {
+	if is_png(data) {
+		Some(InputFormat::Png)
+	} else if data.starts_with(&JPEG_SIGNATURE) {
+		Some(InputFormat::Jpeg)
+	} else if data.starts_with(&BMP_SIGNATURE) {
+		Some(InputFormat::Bmp)
+	} else if GIF_SIGNATURES.iter().any(|signature| data.starts_with(*signature)) {
+		Some(InputFormat::Gif)
+	} else if data.ends_with(TGA_FOOTER_SIGNATURE) {
+		Some(InputFormat::Tga)
+	} else {
+		None
+	}
+}
+
+// Turns the bytes of an input image into a PNG. PNGs pass through untouched so they can still be embedded verbatim.
+// Other supported formats are decoded and re-encoded as 8-bit RGBA PNGs; JPEGs have no alpha, so theirs is opaque.
+// Animated GIFs are converted from their first frame, with a warning through the `log` crate if it's enabled.
+// Unrecognized data is left for the PNG parser to reject.
+pub(crate) fn input_to_png(data: Vec<u8>) -> Result<Vec<u8>> {
+	let format = detect_format(&data);
+	format_to_png(data, format)
+}
+
+// Like `input_to_png`, but reads the file at `path`, which is taken to be a TGA if its contents aren't recognized but
+// its extension is .tga.
+#[cfg(feature = "std")]
+pub(crate) fn read_input(path: &Path) -> Result<Vec<u8>> {
+	let file = std::fs::File::open(path).map_err(PngIcoError::UnreadableFile)?;
+	read_input_from(file, path)
+}
+
+// Like `read_input`, but takes the contents of the file at `path` from `reader`, which is read to the end once.
+#[cfg(feature = "std")]
+pub(crate) fn read_input_from(mut reader: impl Read, path: &Path) -> Result<Vec<u8>> {
+	let mut data = Vec::new();
+	reader.read_to_end(&mut data).map_err(PngIcoError::UnreadableFile)?;
+	let tga = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tga"));
+	let format = detect_format(&data).or(tga.then_some(InputFormat::Tga));
+	format_to_png(data, format)
+}
+
+fn format_to_png(data: Vec<u8>, format: Option<InputFormat>) -> Result<Vec<u8>> {
+	let (width, height, rgba) = match format {
+		Some(InputFormat::Png) | None => return Ok(data),
+		Some(InputFormat::Bmp) => decode_bmp(&data)?,
+		#[cfg(feature = "jpeg")]
+		Some(InputFormat::Jpeg) => decode_jpeg(&data)?,
+		#[cfg(feature = "gif")]
+		Some(InputFormat::Gif) => {
+			let (width, height, rgba, animated) = decode_gif(&data)?;
+			#[cfg(feature = "log")]
+			if animated {
+				log::warn!("GIF is animated; only its first frame is used");
+			}
+			#[cfg(not(feature = "log"))]
+			let _ = animated;
+			(width, height, rgba)
+		}
+		#[cfg(feature = "tga")]
+		Some(InputFormat::Tga) => decode_tga(&data)?,
+		#[allow(unreachable_patterns)]
+		Some(format) => return Err(PngIcoError::UnsupportedFormat(format)),
+	};
+	Ok(encode_png(&rgba, width, height, None, DEFAULT_COMPRESSION_LEVEL))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::rgba_png;
+
+	#[test]
+	fn detects_each_signature() {
+		assert_eq!(detect_format(&rgba_png(1, 1, |_, _| [0; 4])), Some(InputFormat::Png));
+		assert_eq!(detect_format(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 16]), Some(InputFormat::Jpeg));
+		assert_eq!(detect_format(b"BM\x3A\0\0\0"), Some(InputFormat::Bmp));
+		assert_eq!(detect_format(b"GIF87a\x01\0"), Some(InputFormat::Gif));
+		assert_eq!(detect_format(b"GIF89a\x01\0"), Some(InputFormat::Gif));
+		let tga = [&[0; 18][..], &[0; 8], TGA_FOOTER_SIGNATURE].concat();
+		assert_eq!(detect_format(&tga), Some(InputFormat::Tga));
+	}
+
+	#[test]
+	fn leaves_other_data_unrecognized() {
+		let blob = (0..64u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect::<Vec<_>>();
+		assert_eq!(detect_format(&blob), None);
+		for data in [&b""[..], b"\x89PNG", b"\xFF\xD8", b"B", b"GIF88a", b"RIFF\0\0\0\0WEBP"] {
+			assert_eq!(detect_format(data), None, "{:?}", data);
+		}
+	}
+
+	// A 1x1 bottom-up 24bpp BMP of a single blue pixel, whose row is padded to 4 bytes.
+	fn blue_bmp() -> Vec<u8> {
+		let mut bmp = b"BM".to_vec();
+		bmp.extend_from_slice(&58u32.to_le_bytes());
+		bmp.extend_from_slice(&[0; 4]);
+		bmp.extend_from_slice(&54u32.to_le_bytes());
+		bmp.extend_from_slice(&40u32.to_le_bytes());
+		bmp.extend_from_slice(&1i32.to_le_bytes());
+		bmp.extend_from_slice(&1i32.to_le_bytes());
+		bmp.extend_from_slice(&1u16.to_le_bytes());
+		bmp.extend_from_slice(&24u16.to_le_bytes());
+		bmp.extend_from_slice(&[0; 24]);
+		bmp.extend_from_slice(&[255, 0, 0, 0]);
+		bmp
+	}
+
+	#[test]
+	fn dispatches_on_the_contents_rather_than_the_name() {
+		let dir = crate::png::test_support::temp_dir("detect-format");
+		std::fs::write(dir.join("mislabeled.png"), blue_bmp()).unwrap();
+		let png = read_input(&dir.join("mislabeled.png")).unwrap();
+		assert_eq!(crate::png::testing::decode_rgba(&png).unwrap(), (1, 1, vec![0, 0, 255, 255]));
+		std::fs::remove_dir_all(dir).unwrap();
+
+		// Unrecognized data is left for the PNG parser to reject.
+		assert_eq!(input_to_png(b"plain text".to_vec()).unwrap(), b"plain text");
+		#[cfg(not(feature = "jpeg"))]
+		assert!(matches!(
+			input_to_png(vec![0xFF, 0xD8, 0xFF, 0xE0]),
+			Err(PngIcoError::UnsupportedFormat(InputFormat::Jpeg))
+		));
+	}
+}
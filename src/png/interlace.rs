@@ -0,0 +1,175 @@
+use alloc::{vec, vec::Vec};
+
+use super::filter::{filtered_len, unfilter, FilterCounts};
+use crate::{PngIcoError, Result};
+
+// (x offset, y offset, x step, y step) of each Adam7 pass.
+pub(crate) const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+	(0, 0, 8, 8),
+	(4, 0, 8, 8),
+	(0, 4, 4, 8),
+	(2, 0, 4, 4),
+	(0, 2, 2, 4),
+	(1, 0, 2, 2),
+	(0, 1, 1, 2),
+];
+
+fn pass_extent(size: u32, offset: u32, step: u32) -> u32 {
+	if size > offset {
+		(size - offset).div_ceil(step)
+	} else {
+		0
+	}
+}
+
+fn row_len(width: u32, bits_per_pixel: u32) -> usize {
+	(width as usize * bits_per_pixel as usize).div_ceil(8)
+}
+
+fn copy_pixel(src: &[u8], src_x: usize, dst: &mut [u8], dst_x: usize, bits_per_pixel: usize) {
+	if bits_per_pixel >= 8 {
+		let bytes = bits_per_pixel / 8;
+		dst[dst_x * bytes..(dst_x + 1) * bytes].copy_from_slice(&src[src_x * bytes..(src_x + 1) * bytes]);
+	} else {
+		// Sub-byte pixels are packed most significant bits first.
+		let mask = (1u8 << bits_per_pixel) - 1;
+		let src_bit = src_x * bits_per_pixel;
+		let value = (src[src_bit / 8] >> (8 - bits_per_pixel - src_bit % 8)) & mask;
+		let dst_bit = dst_x * bits_per_pixel;
+		dst[dst_bit / 8] |= value << (8 - bits_per_pixel - dst_bit % 8);
+	}
+}
+
+// The length of the filtered image data of an interlaced image, the sum of its non-empty passes.
+pub(crate) fn interlaced_len(width: u32, height: u32, bits_per_pixel: u32) -> usize {
+	ADAM7_PASSES
+		.iter()
+		.map(|&(x_offset, y_offset, x_step, y_step)| {
+			let pass_width = pass_extent(width, x_offset, x_step);
+			let pass_height = pass_extent(height, y_offset, y_step);
+			if pass_width == 0 || pass_height == 0 {
+				0
+			} else {
+				filtered_len(pass_width, pass_height, bits_per_pixel)
+			}
+		})
+		.sum()
+}
+
+// Unfilters each of the seven Adam7 passes and scatters their pixels into a single packed framebuffer.
+pub(crate) fn deinterlace(
+	data: &[u8],
+	width: u32,
+	height: u32,
+	bits_per_pixel: u32,
+	counts: &mut FilterCounts,
+) -> Result<Vec<u8>> {
+	let out_row_len = row_len(width, bits_per_pixel);
+	let mut out = vec![0u8; out_row_len * height as usize];
+	let mut pos = 0;
+	for &(x_offset, y_offset, x_step, y_step) in &ADAM7_PASSES {
+		let pass_width = pass_extent(width, x_offset, x_step);
+		let pass_height = pass_extent(height, y_offset, y_step);
+		// Empty passes are omitted from the stream entirely.
+		if pass_width == 0 || pass_height == 0 {
+			continue;
+		}
+
+		let pass_row_len = row_len(pass_width, bits_per_pixel);
+		let pass_len = filtered_len(pass_width, pass_height, bits_per_pixel);
+		let pass_data = data
+			.get(pos..pos + pass_len)
+			.ok_or(PngIcoError::InvalidImageData("not enough image data for the declared size"))?;
+		pos += pass_len;
+
+		let pass = unfilter(pass_data, pass_width, pass_height, bits_per_pixel, counts)?;
+		for pass_y in 0..pass_height as usize {
+			let src = &pass[pass_y * pass_row_len..(pass_y + 1) * pass_row_len];
+			let y = y_offset as usize + pass_y * y_step as usize;
+			let dst = &mut out[y * out_row_len..(y + 1) * out_row_len];
+			for pass_x in 0..pass_width as usize {
+				let x = x_offset as usize + pass_x * x_step as usize;
+				copy_pixel(src, pass_x, dst, x, bits_per_pixel as usize);
+			}
+		}
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::testing::{decode_rgba, sample_pngs};
+
+	// Packed rows of a `width` x `height` image whose bytes all differ from their neighbours.
+	fn pixels(width: u32, height: u32, bits_per_pixel: u32) -> Vec<u8> {
+		let len = row_len(width, bits_per_pixel) * height as usize;
+		let mut out = (0..len).map(|i| (i as u8).wrapping_mul(37).wrapping_add(11)).collect::<Vec<_>>();
+		// Clear the padding bits at the end of each row, which deinterlacing leaves at zero.
+		let padding = (8 - width * bits_per_pixel % 8) % 8;
+		for row in out.chunks_mut(row_len(width, bits_per_pixel)) {
+			*row.last_mut().unwrap() &= 0xFF << padding;
+		}
+		out
+	}
+
+	// The Adam7 pass stream of `image`, each row stored with filter 0.
+	fn interlace(image: &[u8], width: u32, height: u32, bits_per_pixel: u32) -> Vec<u8> {
+		let mut out = Vec::new();
+		for &(x_offset, y_offset, x_step, y_step) in &ADAM7_PASSES {
+			let pass_width = pass_extent(width, x_offset, x_step) as usize;
+			for y in (y_offset..height).step_by(y_step as usize) {
+				if pass_width == 0 {
+					break;
+				}
+				let src = &image[y as usize * row_len(width, bits_per_pixel)..];
+				let mut row = vec![0; row_len(pass_width as u32, bits_per_pixel)];
+				for pass_x in 0..pass_width {
+					let x = x_offset as usize + pass_x * x_step as usize;
+					copy_pixel(src, x, &mut row, pass_x, bits_per_pixel as usize);
+				}
+				out.push(0);
+				out.extend(row);
+			}
+		}
+		out
+	}
+
+	#[test]
+	fn deinterlaces_images_smaller_than_a_pass_block() {
+		for bits_per_pixel in [1, 2, 4, 8, 24, 64] {
+			for width in 1..8 {
+				for height in 1..8 {
+					let image = pixels(width, height, bits_per_pixel);
+					let stream = interlace(&image, width, height, bits_per_pixel);
+					let mut counts = FilterCounts::default();
+					let deinterlaced = deinterlace(&stream, width, height, bits_per_pixel, &mut counts).unwrap();
+					assert_eq!(deinterlaced, image, "{}x{} at {}bpp", width, height, bits_per_pixel);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn omits_empty_passes() {
+		// A single pixel is all in the first pass, so the stream is one filter byte and one pixel.
+		let mut counts = FilterCounts::default();
+		assert_eq!(deinterlace(&[0, 42], 1, 1, 8, &mut counts).unwrap(), [42]);
+		assert_eq!(counts, [1, 0, 0, 0, 0]);
+		// A 1-pixel-wide column has no pixels in the passes that start at x > 0.
+		let image = pixels(1, 7, 8);
+		let stream = interlace(&image, 1, 7, 8);
+		assert_eq!(stream.len(), 2 * 7);
+		assert!(deinterlace(&stream[..stream.len() - 1], 1, 7, 8, &mut counts).is_err());
+	}
+
+	#[test]
+	fn interlaced_samples_match_their_progressive_counterparts() {
+		let samples = sample_pngs();
+		for (name, png) in &samples {
+			let Some(progressive) = name.strip_suffix(" interlaced") else { continue };
+			let (_, progressive) = samples.iter().find(|(sample, _)| sample == progressive).unwrap();
+			assert_eq!(decode_rgba(png).unwrap(), decode_rgba(progressive).unwrap(), "{}", name);
+		}
+	}
+}
@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use memmap2::{Mmap, MmapOptions};
+
+use super::chunk::png_len;
+use super::ico_writer::{embeddable_png, encode_directory, ResourceKind};
+use super::{PngMetadata, PngParser};
+use crate::{PngIcoError, Result};
+
+// Maps the whole of the file at `path` read-only.
+fn map(path: &Path) -> Result<Mmap> {
+	let file = File::open(path).map_err(PngIcoError::UnreadableFile)?;
+	// Every byte is read, so on Linux the pages are faulted in up front rather than one at a time.
+	// SAFETY: the mapping is only read, and `png_to_ico_mapped` documents that the file must not change meanwhile.
+	unsafe { MmapOptions::new().populate().map(&file) }.map_err(PngIcoError::UnreadableFile)
+}
+
+/// Like [`png_to_ico`](super::png_to_ico), but memory-maps the input instead of reading it onto the heap, and
+/// streams it straight into the output.
+///
+/// Mapping has a fixed setup cost that reading doesn't. Measured with the `mmap` benchmark on Linux, the two take
+/// the same time to within noise from 4 KiB to 1 MiB, and mapping is about 5% faster at 4 MiB, so the crossover is
+/// somewhere past 1 MiB. Below that the gain is in memory, not speed: it never needs a heap buffer the size of the
+/// file.
+/// 16-bit PNGs still have to be decoded and re-encoded, which happens on the heap either way.
+///
+/// The input must not be modified while the conversion is running, or the output may contain a mix of both versions.
+pub fn png_to_ico_mapped(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+	let input = map(input.as_ref())?;
+	let parser = PngParser::new();
+	let metadata = parser.parse_header_bytes(&input)?;
+	if metadata.bit_depth() == 16 {
+		let (metadata, png) = embeddable_png(&parser, input.to_vec())?;
+		return write_streamed(output.as_ref(), &[(&metadata, &png)]);
+	}
+	write_streamed(output.as_ref(), &[(&metadata, &input[..png_len(&input)])])
+}
+
+fn write_streamed(output: &Path, entries: &[(&PngMetadata, &[u8])]) -> Result<()> {
+	let lens = entries.iter().map(|(png, payload)| (*png, payload.len())).collect::<Vec<_>>();
+	let directory = encode_directory(&ResourceKind::ICON, &lens)?;
+	let output = output.with_extension(ResourceKind::ICON.extension());
+	let mut writer = BufWriter::new(File::create(output).map_err(PngIcoError::UnwritableFile)?);
+	writer.write_all(&directory).map_err(PngIcoError::UnwritableFile)?;
+	for (_, payload) in entries {
+		writer.write_all(payload).map_err(PngIcoError::UnwritableFile)?;
+	}
+	writer.flush().map_err(PngIcoError::UnwritableFile)
+}
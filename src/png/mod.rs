@@ -0,0 +1,59 @@
+mod ancillary;
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "std")]
+mod batch;
+mod chunk;
+mod color;
+#[cfg(feature = "std")]
+mod conversion;
+mod crc;
+mod deflate;
+mod encoder;
+mod filter;
+#[cfg(feature = "hash")]
+mod hash;
+mod icns_writer;
+mod ico_reader;
+mod ico_writer;
+#[cfg(feature = "std")]
+mod icon_set;
+mod inflate;
+mod input;
+mod interlace;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod palette;
+mod png_parser;
+#[cfg(feature = "std")]
+mod quantize;
+mod rgba;
+#[cfg(test)]
+pub(crate) mod test_support;
+// Also built for the crate's own tests, which use its sample images.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use ancillary::{ColorSpace, DecodeInfo, Transparency};
+#[cfg(feature = "tokio")]
+pub use async_io::write_ico_async;
+#[cfg(feature = "std")]
+pub use batch::{convert_dir, convert_dir_with_progress, DirConversion};
+pub use chunk::{ChunkIter, RawChunk};
+pub use color::ColorType;
+#[cfg(feature = "hash")]
+pub use hash::rgba_hash;
+#[cfg(feature = "std")]
+pub use conversion::{ConversionBuilder, PlannedEntry};
+pub use icns_writer::*;
+pub use ico_reader::*;
+pub use ico_writer::*;
+#[cfg(feature = "std")]
+pub use icon_set::{
+	generate_icon_set, generate_icon_set_with_depths, generate_icon_set_with_source, png_to_ico_scaled,
+	png_to_ico_with_policy, suggested_sizes, NonSquarePolicy, STANDARD_SIZES,
+};
+pub use input::{detect_format, InputFormat};
+#[cfg(feature = "mmap")]
+pub use mapped::png_to_ico_mapped;
+pub use png_parser::*;
@@ -0,0 +1,102 @@
+use alloc::vec::Vec;
+
+use crate::{PngIcoError, Result};
+
+// Reads the `x`th sample of a packed row. Samples narrower than a byte are stored most significant bits first.
+pub(crate) fn read_sample(row: &[u8], x: usize, bit_depth: u8) -> u16 {
+	match bit_depth {
+		16 => u16::from_be_bytes([row[x * 2], row[x * 2 + 1]]),
+		8 => row[x] as u16,
+		_ => {
+			let bits = bit_depth as usize;
+			let bit = x * bits;
+			((row[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1)) as u16
+		}
+	}
+}
+
+// Maps every palette index to its RGBA color. Entries missing from `alpha` are fully opaque.
+pub(crate) fn expand_palette(
+	data: &[u8],
+	width: u32,
+	height: u32,
+	bit_depth: u8,
+	palette: &[u8],
+	alpha: &[u8],
+) -> Result<Vec<u8>> {
+	let row_len = (width as usize * bit_depth as usize).div_ceil(8);
+	let mut out = Vec::with_capacity(width as usize * height as usize * 4);
+	for row in data.chunks(row_len.max(1)).take(height as usize) {
+		for x in 0..width as usize {
+			let index = read_sample(row, x, bit_depth) as usize;
+			let color = palette
+				.get(index * 3..index * 3 + 3)
+				.ok_or(PngIcoError::InvalidImageData("palette index out of range"))?;
+			out.extend_from_slice(color);
+			out.push(alpha.get(index).copied().unwrap_or(255));
+		}
+	}
+	Ok(out)
+}
+
+// The palette as RGBA colors, taking alpha from tRNS. Entries missing from `alpha` are fully opaque.
+pub(crate) fn palette_colors(palette: &[u8], alpha: &[u8]) -> Vec<[u8; 4]> {
+	palette
+		.chunks(3)
+		.enumerate()
+		.map(|(index, rgb)| [rgb[0], rgb[1], rgb[2], alpha.get(index).copied().unwrap_or(255)])
+		.collect()
+}
+
+// Unpacks one palette index per pixel, checking each against the number of palette entries.
+pub(crate) fn unpack_indices(data: &[u8], width: u32, height: u32, bit_depth: u8, colors: usize) -> Result<Vec<u8>> {
+	let row_len = (width as usize * bit_depth as usize).div_ceil(8);
+	let mut out = Vec::with_capacity(width as usize * height as usize);
+	for row in data.chunks(row_len.max(1)).take(height as usize) {
+		for x in 0..width as usize {
+			let index = read_sample(row, x, bit_depth) as usize;
+			if index >= colors {
+				return Err(PngIcoError::InvalidImageData("palette index out of range"));
+			}
+			out.push(index as u8);
+		}
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::png_with_chunks;
+	use crate::png::{ColorType, PngParser};
+
+	const PALETTE: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+
+	// A 3x2 image of 2-bit indices 0, 1, 2 over 3, 2, 1, with tRNS making index 1 transparent.
+	fn indexed_png(alpha: &[u8]) -> Vec<u8> {
+		let chunks: &[(&[u8; 4], &[u8])] = &[(b"PLTE", &PALETTE), (b"tRNS", alpha)];
+		png_with_chunks((3, 2), 2, ColorType::Indexed, chunks, &[0b0001_1000, 0b1110_0100])
+	}
+
+	#[test]
+	fn expands_indices_to_their_palette_colors() {
+		let decoded = PngParser::new().decode_bytes(&indexed_png(&[255, 0])).unwrap();
+		let (red, clear_green, blue, white) = ([255, 0, 0, 255], [0, 255, 0, 0], [0, 0, 255, 255], [255; 4]);
+		assert_eq!(decoded.png_data, [red, clear_green, blue, white, blue, clear_green].concat());
+	}
+
+	#[test]
+	fn keeps_indices_and_the_palette_apart() {
+		let image = PngParser::new().decode_indexed_bytes(&indexed_png(&[255, 0])).unwrap().unwrap();
+		assert_eq!(image.indices, [0, 1, 2, 3, 2, 1]);
+		assert_eq!(image.palette, [[255, 0, 0, 255], [0, 255, 0, 0], [0, 0, 255, 255], [255; 4]]);
+	}
+
+	#[test]
+	fn rejects_indices_past_the_palette() {
+		assert_eq!(expand_palette(&[0b1100_0000], 1, 1, 2, &PALETTE, &[]).unwrap(), [255; 4]);
+		let short = expand_palette(&[0b1100_0000], 1, 1, 2, &PALETTE[..9], &[]);
+		assert!(matches!(short, Err(PngIcoError::InvalidImageData(_))));
+		assert!(unpack_indices(&[0b1100_0000], 1, 1, 2, 3).is_err());
+	}
+}
@@ -0,0 +1,965 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use super::ancillary::{
+	parse_animation_control, parse_compressed_text, parse_gamma, parse_international_text, parse_physical_dimensions,
+	parse_srgb, parse_text, ColorSpace, DecodeInfo, Transparency,
+};
+use super::chunk::{png_len, ChunkIter};
+use super::color::ColorType;
+use super::crc::crc32;
+use super::filter::{filtered_len, unfilter, FilterCounts};
+use super::inflate::zlib_decompress;
+use super::interlace::{deinterlace, interlaced_len};
+use super::palette::{expand_palette, palette_colors, unpack_indices};
+use crate::{PngIcoError, Result};
+
+/// The eight bytes every PNG file starts with.
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Whether `data` starts with the PNG signature, as a cheap check before parsing. Inputs shorter than the signature
+/// are not PNGs. Only the signature is checked, so the rest of the data may still be invalid.
+pub fn is_png(data: &[u8]) -> bool {
+	data.starts_with(&PNG_SIGNATURE)
+}
+
+/// The signature plus an IHDR chunk, the smallest prefix that can hold the image header.
+pub const MIN_PNG_SIZE: usize = 8 + 12 + 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PngMetadata {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	pub compression_method: u8,
+	pub filter_method: u8,
+	pub interlace_method: u8,
+}
+
+impl PngMetadata {
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+
+	/// Bits per sample, or per palette index for indexed images.
+	pub fn bit_depth(&self) -> u8 {
+		self.bit_depth
+	}
+
+	/// The raw color type byte; [`color`](Self::color) has the named form.
+	pub fn color_type(&self) -> u8 {
+		self.color_type
+	}
+
+	pub fn compression_method(&self) -> u8 {
+		self.compression_method
+	}
+
+	pub fn filter_method(&self) -> u8 {
+		self.filter_method
+	}
+
+	/// 0 for non-interlaced images and 1 for Adam7.
+	pub fn interlace_method(&self) -> u8 {
+		self.interlace_method
+	}
+
+	/// The named form of the raw `color_type` byte.
+	pub fn color(&self) -> Result<ColorType> {
+		ColorType::from_u8(self.color_type)
+	}
+
+	pub(crate) fn bits_per_pixel(&self) -> Result<u32> {
+		Ok(self.color()?.channel_count() * self.bit_depth as u32)
+	}
+
+	/// `width` times `height`, which can exceed `u32::MAX`.
+	pub fn pixel_count(&self) -> u64 {
+		self.width as u64 * self.height as u64
+	}
+
+	/// An estimate of the bytes `decode` needs for the decoded pixels: a whole number of bytes per pixel, or 4 for
+	/// indexed images, which are expanded to RGBA.
+	pub fn decoded_size(&self) -> Result<u64> {
+		let bytes_per_pixel = match self.color()? {
+			ColorType::Indexed => 4,
+			_ => self.bits_per_pixel()?.div_ceil(8) as u64,
+		};
+		Ok(self.pixel_count().saturating_mul(bytes_per_pixel))
+	}
+
+	/// Checks the [`decoded_size`](Self::decoded_size) against `limit`, and returns it if it fits.
+	pub fn check_memory_limit(&self, limit: u64) -> Result<u64> {
+		let size = self.decoded_size()?;
+		if size > limit {
+			return Err(PngIcoError::MemoryLimitExceeded(size, limit));
+		}
+		Ok(size)
+	}
+}
+
+// The header of an image that was decoded and re-encoded as 8-bit RGBA.
+pub(crate) fn rgba_metadata(width: u32, height: u32) -> PngMetadata {
+	PngMetadata {
+		width,
+		height,
+		bit_depth: 8,
+		color_type: ColorType::TruecolorAlpha.to_u8(),
+		compression_method: 0,
+		filter_method: 0,
+		interlace_method: 0,
+	}
+}
+
+// Checks an image of another format against the default memory limit before it's decoded to 8-bit RGBA, since its
+// dimensions come from the file.
+#[cfg(any(feature = "jpeg", feature = "gif", feature = "tga"))]
+pub(crate) fn check_rgba_size(width: u32, height: u32) -> Result<()> {
+	rgba_metadata(width, height).check_memory_limit(DEFAULT_MEMORY_LIMIT).map(drop)
+}
+
+fn validate_bit_depth(bit_depth: u8, color_type: u8) -> Result<()> {
+	if !ColorType::from_u8(color_type)?.allowed_bit_depths().contains(&bit_depth) {
+		return Err(PngIcoError::InvalidBitDepth(bit_depth, color_type));
+	}
+	Ok(())
+}
+
+// The spec defines a single compression and filter method, and interlacing is either off or Adam7. Any other value
+// means the header is corrupt.
+fn validate_methods(compression_method: u8, filter_method: u8, interlace_method: u8) -> Result<()> {
+	match (compression_method, filter_method, interlace_method) {
+		(1.., _, _) => Err(PngIcoError::InvalidMethod("compression", compression_method)),
+		(_, 1.., _) => Err(PngIcoError::InvalidMethod("filter", filter_method)),
+		(_, _, 2..) => Err(PngIcoError::InvalidMethod("interlace", interlace_method)),
+		_ => Ok(()),
+	}
+}
+
+impl fmt::Display for PngMetadata {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}x{}, {}-bit ", self.width, self.height, self.bit_depth)?;
+		match self.color() {
+			Ok(color) => write!(f, "{}", color.name())?,
+			Err(_) => write!(f, "color type {}", self.color_type)?,
+		}
+		if let Ok(bits_per_pixel) = self.bits_per_pixel() {
+			write!(f, " ({} bpp)", bits_per_pixel)?;
+		}
+		match self.interlace_method {
+			0 => write!(f, ", non-interlaced"),
+			1 => write!(f, ", Adam7 interlaced"),
+			method => write!(f, ", interlace method {}", method),
+		}
+	}
+}
+
+enum ChunkType {
+	Header,
+	Palette,
+	Transparency,
+	PhysicalDimensions,
+	Text,
+	CompressedText,
+	InternationalText,
+	AnimationControl,
+	Srgb,
+	Gamma,
+	Data,
+	End,
+}
+
+impl ChunkType {
+	fn parse(name: &[u8]) -> Result<ChunkType> {
+		match name {
+			b"IHDR" => Ok(ChunkType::Header),
+			b"PLTE" => Ok(ChunkType::Palette),
+			b"tRNS" => Ok(ChunkType::Transparency),
+			b"pHYs" => Ok(ChunkType::PhysicalDimensions),
+			b"tEXt" => Ok(ChunkType::Text),
+			b"zTXt" => Ok(ChunkType::CompressedText),
+			b"iTXt" => Ok(ChunkType::InternationalText),
+			b"acTL" => Ok(ChunkType::AnimationControl),
+			b"sRGB" => Ok(ChunkType::Srgb),
+			b"gAMA" => Ok(ChunkType::Gamma),
+			b"IDAT" => Ok(ChunkType::Data),
+			b"IEND" => Ok(ChunkType::End),
+			_ => Err(PngIcoError::UnknownChunk(String::from_utf8_lossy(name).into_owned())),
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			ChunkType::Header => "IHDR",
+			ChunkType::Palette => "PLTE",
+			ChunkType::Transparency => "tRNS",
+			ChunkType::PhysicalDimensions => "pHYs",
+			ChunkType::Text => "tEXt",
+			ChunkType::CompressedText => "zTXt",
+			ChunkType::InternationalText => "iTXt",
+			ChunkType::AnimationControl => "acTL",
+			ChunkType::Srgb => "sRGB",
+			ChunkType::Gamma => "gAMA",
+			ChunkType::Data => "IDAT",
+			ChunkType::End => "IEND",
+		}
+	}
+}
+
+enum Chunk<'a> {
+	Header(PngMetadata),
+	Palette(&'a [u8]),
+	Transparency(&'a [u8]),
+	PhysicalDimensions((u32, u32, bool)),
+	Text((String, String)),
+	AnimationControl(u32),
+	Srgb(u8),
+	Gamma(u32),
+	Data(&'a [u8]),
+	End,
+}
+
+// The chunks of a PNG that are needed to decode it.
+struct ChunkStream<'a> {
+	metadata: PngMetadata,
+	palette: Option<&'a [u8]>,
+	info: DecodeInfo,
+	data: Vec<&'a [u8]>,
+}
+
+// An indexed image with one palette index per pixel, and the palette as RGBA colors.
+pub(crate) struct IndexedImage {
+	pub metadata: PngMetadata,
+	pub indices: Vec<u8>,
+	pub palette: Vec<[u8; 4]>,
+}
+
+#[derive(Debug)]
+pub struct EncodedPng {
+	pub metadata: PngMetadata,
+	pub info: DecodeInfo,
+	/// The unfiltered scanline bytes, packed row by row without filter-type bytes.
+	/// Indexed images are expanded to 8-bit RGBA.
+	pub png_data: Vec<u8>,
+	filter_counts: FilterCounts,
+}
+
+impl EncodedPng {
+	/// How many scanlines used each filter type, indexed by type: None, Sub, Up, Average and Paeth. Interlaced
+	/// images count the scanlines of every pass. Encoders that filter every row the same way, or not at all, tend to
+	/// compress worse than those that pick a filter per row.
+	pub fn filter_type_counts(&self) -> [u32; 5] {
+		self.filter_counts
+	}
+}
+
+/// The [`memory_limit`](PngParser::memory_limit) a parser starts with, which also bounds the images of other formats
+/// that are decoded for conversion: 512 MiB, enough for an 8192 by 8192 image with 16-bit RGBA samples.
+pub const DEFAULT_MEMORY_LIMIT: u64 = 512 << 20;
+
+#[derive(Clone)]
+pub struct PngParser {
+	validate_crc: bool,
+	strip_16: bool,
+	memory_limit: u64,
+	strict: bool,
+}
+
+// Reads into `buf` from `filled` on until it is full or the reader runs out, and returns how much of it is filled.
+#[cfg(feature = "std")]
+fn fill(reader: &mut impl std::io::Read, buf: &mut [u8], mut filled: usize) -> Result<usize> {
+	while filled < buf.len() {
+		match reader.read(&mut buf[filled..]) {
+			Ok(0) => break,
+			Ok(read) => filled += read,
+			Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+			Err(err) => return Err(PngIcoError::UnreadableFile(err)),
+		}
+	}
+	Ok(filled)
+}
+
+impl Default for PngParser {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PngParser {
+	pub fn new() -> Self {
+		PngParser {
+			validate_crc: true,
+			strip_16: false,
+			memory_limit: DEFAULT_MEMORY_LIMIT,
+			strict: false,
+		}
+	}
+
+	/// Toggles CRC-32 validation of parsed chunks. Enabled by default.
+	pub fn validate_crc(mut self, validate: bool) -> Self {
+		self.validate_crc = validate;
+		self
+	}
+
+	/// Makes `decode` convert 16-bit images to 8 bits per sample by keeping the high byte of each sample.
+	/// Gray and RGB tRNS colors are reduced the same way. Disabled by default.
+	pub fn strip_16(mut self, strip: bool) -> Self {
+		self.strip_16 = strip;
+		self
+	}
+
+	/// Makes decoding fail with [`MemoryLimitExceeded`](PngIcoError::MemoryLimitExceeded) before inflating any image
+	/// data, if the header claims an image whose [`decoded_size`](PngMetadata::decoded_size) is over `limit` bytes.
+	/// The image data may then inflate only to the length its header implies, and zTXt and iTXt text to at most
+	/// `limit` bytes. Defaults to [`DEFAULT_MEMORY_LIMIT`]; `u64::MAX` turns the check off.
+	pub fn memory_limit(mut self, limit: u64) -> Self {
+		self.memory_limit = limit;
+		self
+	}
+
+	/// Rejects files that break spec rules many decoders tolerate, when decoding or reading their [`DecodeInfo`]:
+	/// IEND must be present, empty and last, and critical chunks must have valid CRCs even when
+	/// [`validate_crc`](Self::validate_crc) is off. IHDR must come first in either mode. Disabled by default, when
+	/// these are only reported as warnings with the `log` feature.
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	// Reports a spec violation that many decoders tolerate: an error in strict mode, otherwise a warning.
+	fn nonconformant(&self, error: PngIcoError) -> Result<()> {
+		if self.strict {
+			return Err(error);
+		}
+		#[cfg(feature = "log")]
+		log::warn!("{}", error);
+		#[cfg(not(feature = "log"))]
+		let _ = error;
+		Ok(())
+	}
+
+	// Reports the first byte that differs from the signature, checking only as many bytes as there are.
+	fn verify_signature(&self, data: &[u8]) -> Result<()> {
+		match data.iter().zip(&PNG_SIGNATURE).position(|(byte, expected)| byte != expected) {
+			Some(position) => Err(PngIcoError::BadSignature(position, data[position])),
+			None => Ok(()),
+		}
+	}
+
+	fn parse_header_chunk(&self, chunk_data: &[u8]) -> Result<PngMetadata> {
+		if chunk_data.len() != 13 {
+			return Err(PngIcoError::InvalidChunkLength(String::from("IHDR")));
+		}
+		validate_bit_depth(chunk_data[8], chunk_data[9])?;
+		validate_methods(chunk_data[10], chunk_data[11], chunk_data[12])?;
+		let mut width = [0; 4];
+		width.copy_from_slice(&chunk_data[..4]);
+		let mut height = [0; 4];
+		height.copy_from_slice(&chunk_data[4..8]);
+		let (width, height) = (u32::from_be_bytes(width), u32::from_be_bytes(height));
+		if width == 0 || height == 0 {
+			return Err(PngIcoError::ZeroDimension(width, height));
+		}
+		Ok(PngMetadata {
+			width,
+			height,
+			bit_depth: chunk_data[8],
+			color_type: chunk_data[9],
+			compression_method: chunk_data[10],
+			filter_method: chunk_data[11],
+			interlace_method: chunk_data[12],
+		})
+	}
+
+	// Compressed text has no declared size, so it may inflate to at most the memory limit.
+	fn text_limit(&self) -> usize {
+		usize::try_from(self.memory_limit).unwrap_or(usize::MAX)
+	}
+
+	fn parse_chunk<'a>(&self, chunk_type: ChunkType, chunk_data: &'a [u8], crc: u32) -> Result<Chunk<'a>> {
+		if self.validate_crc && crc32(&[chunk_type.name().as_bytes(), chunk_data]) != crc {
+			return Err(PngIcoError::CrcMismatch(chunk_type.name().to_string()));
+		}
+		match chunk_type {
+			ChunkType::Header => Ok(Chunk::Header(self.parse_header_chunk(chunk_data)?)),
+			ChunkType::Data => Ok(Chunk::Data(chunk_data)),
+			ChunkType::End => Ok(Chunk::End),
+			ChunkType::Palette => {
+				if !chunk_data.len().is_multiple_of(3) || chunk_data.len() > 256 * 3 {
+					return Err(PngIcoError::InvalidPalette);
+				}
+				Ok(Chunk::Palette(chunk_data))
+			}
+			ChunkType::Transparency => Ok(Chunk::Transparency(chunk_data)),
+			ChunkType::PhysicalDimensions => Ok(Chunk::PhysicalDimensions(parse_physical_dimensions(chunk_data)?)),
+			ChunkType::Text => Ok(Chunk::Text(parse_text(chunk_data)?)),
+			ChunkType::CompressedText => Ok(Chunk::Text(parse_compressed_text(chunk_data, self.text_limit())?)),
+			ChunkType::InternationalText => Ok(Chunk::Text(parse_international_text(chunk_data, self.text_limit())?)),
+			ChunkType::AnimationControl => Ok(Chunk::AnimationControl(parse_animation_control(chunk_data)?)),
+			ChunkType::Srgb => Ok(Chunk::Srgb(parse_srgb(chunk_data)?)),
+			ChunkType::Gamma => Ok(Chunk::Gamma(parse_gamma(chunk_data)?)),
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn parse_header(&self, path: impl AsRef<Path>) -> Result<PngMetadata> {
+		let data = std::fs::read(path.as_ref()).map_err(PngIcoError::UnreadableFile)?;
+		self.parse_header_bytes(&data)
+	}
+
+	/// Like [`parse_header_bytes`](Self::parse_header_bytes), but reads only the signature and IHDR from `reader`,
+	/// leaving the rest of the stream unread. It stops as soon as the signature or chunk header turns out to be invalid.
+	#[cfg(feature = "std")]
+	pub fn parse_header_from_reader(&self, mut reader: impl std::io::Read) -> Result<PngMetadata> {
+		let mut header = [0; MIN_PNG_SIZE];
+		let signature_len = PNG_SIGNATURE.len();
+		let read = fill(&mut reader, &mut header[..signature_len], 0)?;
+		self.verify_signature(&header[..read])?;
+		let read = fill(&mut reader, &mut header[..signature_len + 8], read)?;
+		if read >= signature_len + 8 {
+			if &header[signature_len + 4..signature_len + 8] != b"IHDR" {
+				return Err(PngIcoError::MissingHeader);
+			}
+			if header[signature_len..signature_len + 4] != 13u32.to_be_bytes() {
+				return Err(PngIcoError::InvalidChunkLength(String::from("IHDR")));
+			}
+		}
+		let read = fill(&mut reader, &mut header, read)?;
+		self.parse_header_bytes(&header[..read])
+	}
+
+	pub fn chunks<'a>(&self, data: &'a [u8]) -> Result<ChunkIter<'a>> {
+		self.verify_signature(data)?;
+		if data.len() < MIN_PNG_SIZE {
+			return Err(PngIcoError::FileTooShort(data.len()));
+		}
+		Ok(ChunkIter::new(&data[8..]))
+	}
+
+	/// Reads the IHDR chunk, which the spec requires to be the first chunk. Its length is taken from the chunk itself.
+	pub fn parse_header_bytes(&self, data: &[u8]) -> Result<PngMetadata> {
+		let chunk = self.chunks(data)?.next().ok_or(PngIcoError::MissingHeader)??;
+		match ChunkType::parse(&chunk.name) {
+			Ok(chunk_type @ ChunkType::Header) => match self.parse_chunk(chunk_type, chunk.data, chunk.crc)? {
+				Chunk::Header(metadata) => Ok(metadata),
+				_ => Err(PngIcoError::MissingHeader),
+			},
+			_ => Err(PngIcoError::MissingHeader),
+		}
+	}
+
+	/// Finds every PNG in `data`, such as a blob of sprites stored back to back, and returns each one's offset and
+	/// header. After each PNG's IEND the search carries on for the next signature, skipping any bytes in between. A
+	/// PNG without an IEND runs to the end of `data`.
+	pub fn parse_all_headers(&self, data: &[u8]) -> Result<Vec<(usize, PngMetadata)>> {
+		let mut headers = Vec::new();
+		let mut pos = 0;
+		while let Some(found) = data[pos..].windows(PNG_SIGNATURE.len()).position(|window| window == PNG_SIGNATURE) {
+			let start = pos + found;
+			headers.push((start, self.parse_header_bytes(&data[start..])?));
+			pos = start + png_len(&data[start..]);
+		}
+		Ok(headers)
+	}
+
+	fn read_chunks<'a>(&self, data: &'a [u8]) -> Result<ChunkStream<'a>> {
+		let mut metadata = None;
+		let mut palette = None;
+		let mut transparency = None;
+		let mut physical_dimensions = None;
+		let mut text = Vec::new();
+		let mut frame_count = None;
+		let mut srgb = None;
+		let mut gamma = None;
+		let mut image_data = Vec::new();
+		let mut ended = false;
+		let mut chunks = self.chunks(data)?;
+		for (index, chunk) in chunks.by_ref().enumerate() {
+			let chunk = chunk?;
+			if index == 0 && &chunk.name != b"IHDR" {
+				return Err(PngIcoError::MissingHeader);
+			}
+			// With validation on, mismatches in known chunks are already errors.
+			if !self.validate_crc && chunk.name[0].is_ascii_uppercase() && !chunk.crc_matches() {
+				self.nonconformant(PngIcoError::CrcMismatch(String::from_utf8_lossy(&chunk.name).into_owned()))?;
+			}
+			if &chunk.name == b"IEND" && !chunk.data.is_empty() {
+				self.nonconformant(PngIcoError::Nonconformant("IEND is not empty"))?;
+			}
+			let chunk_type = match ChunkType::parse(&chunk.name) {
+				Ok(chunk_type) => chunk_type,
+				// Ancillary chunks are marked by a lowercase first letter and are safe to skip.
+				Err(_) if chunk.name[0].is_ascii_lowercase() => continue,
+				Err(err) => return Err(err),
+			};
+			match self.parse_chunk(chunk_type, chunk.data, chunk.crc)? {
+				Chunk::Header(header) => metadata = Some(header),
+				Chunk::Palette(entries) => palette = Some(entries),
+				Chunk::Transparency(alpha) => transparency = Some(alpha),
+				Chunk::PhysicalDimensions(dimensions) => physical_dimensions = Some(dimensions),
+				Chunk::Text(entry) => text.push(entry),
+				// Only the default image is decoded, and the fcTL and fdAT chunks are skipped as ancillary.
+				Chunk::AnimationControl(frames) => frame_count = Some(frames),
+				Chunk::Srgb(intent) => srgb = Some(intent),
+				Chunk::Gamma(value) => gamma = Some(value),
+				// Image data may be split across several consecutive IDAT chunks.
+				Chunk::Data(data) => image_data.push(data),
+				Chunk::End => {
+					ended = true;
+					break;
+				}
+			}
+		}
+		if !ended {
+			self.nonconformant(PngIcoError::Nonconformant("there is no IEND chunk"))?;
+		} else if chunks.trailing_len() > 0 {
+			self.nonconformant(PngIcoError::Nonconformant("data follows IEND"))?;
+		}
+
+		let metadata = metadata.ok_or(PngIcoError::MissingHeader)?;
+		let info = DecodeInfo {
+			transparency: transparency
+				.map(|data| Transparency::parse(data, metadata.color_type))
+				.transpose()?,
+			physical_dimensions,
+			text,
+			frame_count,
+			color_space: srgb.map(ColorSpace::Srgb).or(gamma.map(ColorSpace::Gamma)),
+		};
+		Ok(ChunkStream {
+			metadata,
+			palette,
+			info,
+			data: image_data,
+		})
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decode_info(&self, path: impl AsRef<Path>) -> Result<DecodeInfo> {
+		let data = std::fs::read(path.as_ref()).map_err(PngIcoError::UnreadableFile)?;
+		self.decode_info_bytes(&data)
+	}
+
+	pub fn decode_info_bytes(&self, data: &[u8]) -> Result<DecodeInfo> {
+		Ok(self.read_chunks(data)?.info)
+	}
+
+	#[cfg(feature = "std")]
+	pub fn text_metadata(&self, path: impl AsRef<Path>) -> Result<Vec<(String, String)>> {
+		Ok(self.decode_info(path)?.text)
+	}
+
+	pub fn text_metadata_bytes(&self, data: &[u8]) -> Result<Vec<(String, String)>> {
+		Ok(self.decode_info_bytes(data)?.text)
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decode(&self, path: impl AsRef<Path>) -> Result<EncodedPng> {
+		let data = std::fs::read(path.as_ref()).map_err(PngIcoError::UnreadableFile)?;
+		self.decode_bytes(&data)
+	}
+
+	// Inflates and unfilters the image data into packed rows of samples, counting the filter types along the way.
+	fn read_samples<'a>(&self, data: &'a [u8]) -> Result<(ChunkStream<'a>, Vec<u8>, FilterCounts)> {
+		let stream = self.read_chunks(data)?;
+		let metadata = &stream.metadata;
+		let bits_per_pixel = metadata.bits_per_pixel()?;
+		metadata.check_memory_limit(self.memory_limit)?;
+		// Rows past the last one would be thrown away, so inflating stops with an error once the stream holds more.
+		let expected_len = match metadata.interlace_method {
+			1 => interlaced_len(metadata.width, metadata.height, bits_per_pixel),
+			_ => filtered_len(metadata.width, metadata.height, bits_per_pixel),
+		};
+		let filtered = zlib_decompress(&stream.data.concat(), expected_len)?;
+		let mut counts = FilterCounts::default();
+		let samples = match metadata.interlace_method {
+			0 => unfilter(&filtered, metadata.width, metadata.height, bits_per_pixel, &mut counts)?,
+			_ => deinterlace(&filtered, metadata.width, metadata.height, bits_per_pixel, &mut counts)?,
+		};
+		Ok((stream, samples, counts))
+	}
+
+	// Decodes an indexed PNG without expanding its palette. Returns None for other color types.
+	pub(crate) fn decode_indexed_bytes(&self, data: &[u8]) -> Result<Option<IndexedImage>> {
+		let (stream, samples, _) = self.read_samples(data)?;
+		let metadata = stream.metadata;
+		if metadata.color()? != ColorType::Indexed {
+			return Ok(None);
+		}
+		let palette = stream.palette.ok_or(PngIcoError::MissingPalette)?;
+		let alpha = match &stream.info.transparency {
+			Some(Transparency::Palette(alpha)) => &alpha[..],
+			_ => &[],
+		};
+		let palette = palette_colors(palette, alpha);
+		let indices = unpack_indices(&samples, metadata.width, metadata.height, metadata.bit_depth, palette.len())?;
+		Ok(Some(IndexedImage {
+			metadata,
+			indices,
+			palette,
+		}))
+	}
+
+	pub fn decode_bytes(&self, data: &[u8]) -> Result<EncodedPng> {
+		let (
+			ChunkStream {
+				mut metadata,
+				palette,
+				mut info,
+				..
+			},
+			png_data,
+			filter_counts,
+		) = self.read_samples(data)?;
+		let png_data = if self.strip_16 && metadata.bit_depth == 16 {
+			metadata.bit_depth = 8;
+			info.transparency = info.transparency.map(|transparency| match transparency {
+				Transparency::Gray(gray) => Transparency::Gray(gray >> 8),
+				Transparency::Rgb(r, g, b) => Transparency::Rgb(r >> 8, g >> 8, b >> 8),
+				palette => palette,
+			});
+			png_data.iter().step_by(2).copied().collect()
+		} else {
+			png_data
+		};
+		let png_data = if metadata.color()? == ColorType::Indexed {
+			let palette = palette.ok_or(PngIcoError::MissingPalette)?;
+			let alpha = match &info.transparency {
+				Some(Transparency::Palette(alpha)) => &alpha[..],
+				_ => &[],
+			};
+			expand_palette(
+				&png_data,
+				metadata.width,
+				metadata.height,
+				metadata.bit_depth,
+				palette,
+				alpha,
+			)?
+		} else {
+			png_data
+		};
+		Ok(EncodedPng {
+			metadata,
+			info,
+			png_data,
+			filter_counts,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(feature = "log")]
+	use crate::png::test_support::captured_warnings;
+	use crate::png::deflate::zlib_compress;
+	use crate::png::test_support::{png_with_chunks, rgba_png, temp_dir, write_chunk};
+
+	#[test]
+	fn rejects_chunks_with_a_wrong_crc() {
+		let mut png = rgba_png(2, 2, |_, _| [1, 2, 3, 255]);
+		png[32] ^= 1;
+		let err = PngParser::new().parse_header_bytes(&png).unwrap_err();
+		assert!(matches!(&err, PngIcoError::CrcMismatch(name) if name == "IHDR"));
+		assert_eq!(err.to_string(), "CRC mismatch in IHDR chunk.");
+		assert!(matches!(PngParser::new().decode_bytes(&png), Err(PngIcoError::CrcMismatch(_))));
+	}
+
+	#[test]
+	fn crc_validation_can_be_turned_off() {
+		let mut png = rgba_png(2, 2, |_, _| [1, 2, 3, 255]);
+		png[32] ^= 1;
+		let parser = PngParser::new().validate_crc(false);
+		assert_eq!(parser.parse_header_bytes(&png).unwrap().width(), 2);
+		assert_eq!(parser.decode_bytes(&png).unwrap().png_data, [1, 2, 3, 255].repeat(4));
+		// Strict mode still checks critical chunks.
+		assert!(matches!(parser.strict(true).decode_bytes(&png), Err(PngIcoError::CrcMismatch(_))));
+	}
+
+	#[test]
+	fn accepts_only_the_spec_bit_depths_for_each_color_type() {
+		let valid: [(u8, &[u8]); 5] =
+			[(0, &[1, 2, 4, 8, 16]), (2, &[8, 16]), (3, &[1, 2, 4, 8]), (4, &[8, 16]), (6, &[8, 16])];
+		for (color_type, depths) in valid {
+			for bit_depth in [0, 1, 2, 3, 4, 8, 16, 32] {
+				let result = validate_bit_depth(bit_depth, color_type);
+				if depths.contains(&bit_depth) {
+					assert!(result.is_ok(), "{} bits for color type {}", bit_depth, color_type);
+				} else {
+					assert!(
+						matches!(result, Err(PngIcoError::InvalidBitDepth(d, c)) if (d, c) == (bit_depth, color_type)),
+						"{} bits for color type {}",
+						bit_depth,
+						color_type
+					);
+				}
+			}
+		}
+		for color_type in [1, 5, 7, 255] {
+			let result = validate_bit_depth(8, color_type);
+			assert!(matches!(result, Err(PngIcoError::InvalidColorType(c)) if c == color_type));
+		}
+	}
+
+	#[test]
+	fn rejects_headers_with_an_illegal_pair() {
+		let mut png = rgba_png(1, 1, |_, _| [0; 4]);
+		png[24] = 4;
+		let crc = crc32(&[&png[12..29]]);
+		png[29..33].copy_from_slice(&crc.to_be_bytes());
+		let err = PngParser::new().parse_header_bytes(&png).unwrap_err();
+		assert!(matches!(err, PngIcoError::InvalidBitDepth(4, 6)));
+		assert_eq!(err.to_string(), "Bit depth 4 is not allowed for color type 6.");
+	}
+
+	#[test]
+	fn rejects_zero_dimensions() {
+		let err = PngParser::new().parse_header_bytes(&claiming_size(0, 32)).unwrap_err();
+		assert!(matches!(err, PngIcoError::ZeroDimension(0, 32)));
+		assert_eq!(err.to_string(), "PNG has zero width/height (0x32).");
+		assert!(matches!(PngParser::new().decode_bytes(&claiming_size(32, 0)), Err(PngIcoError::ZeroDimension(32, 0))));
+	}
+
+	#[test]
+	fn accepts_only_the_spec_methods() {
+		// The IHDR byte of each method, and the values the spec defines for it.
+		let fields: [(&str, usize, &[u8]); 3] =
+			[("compression", 26, &[0]), ("filter", 27, &[0]), ("interlace", 28, &[0, 1])];
+		for (field, pos, valid) in fields {
+			for method in [0, 1, 2, 255] {
+				let mut png = claiming_size(1, 1);
+				png[pos] = method;
+				let crc = crc32(&[&png[12..29]]);
+				png[29..33].copy_from_slice(&crc.to_be_bytes());
+				match PngParser::new().parse_header_bytes(&png) {
+					Ok(_) => assert!(valid.contains(&method), "{} method {}", field, method),
+					Err(PngIcoError::InvalidMethod(name, value)) => {
+						assert!(!valid.contains(&method));
+						assert_eq!((name, value), (field, method));
+					}
+					Err(err) => panic!("{} method {}: {}", field, method, err),
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn rejects_files_too_short_for_a_header() {
+		let png = rgba_png(1, 1, |_, _| [0; 4]);
+		for len in [0, 8, 20, MIN_PNG_SIZE - 1] {
+			let err = PngParser::new().parse_header_bytes(&png[..len]).unwrap_err();
+			assert!(matches!(err, PngIcoError::FileTooShort(short) if short == len), "{} bytes", len);
+			let message = format!("File too short to contain a PNG header (need >= 33 bytes, got {}).", len);
+			assert_eq!(err.to_string(), message);
+			assert!(matches!(PngParser::new().decode_bytes(&png[..len]), Err(PngIcoError::FileTooShort(_))));
+			let read = PngParser::new().parse_header_from_reader(&png[..len]);
+			assert!(matches!(read, Err(PngIcoError::FileTooShort(short)) if short == len), "{} bytes", len);
+		}
+		assert_eq!(PngParser::new().parse_header_bytes(&png[..MIN_PNG_SIZE]).unwrap().width(), 1);
+	}
+
+	#[test]
+	fn reports_the_first_byte_that_is_not_the_signature() {
+		let png = rgba_png(1, 1, |_, _| [0; 4]);
+		let parse = |data: &[u8]| PngParser::new().parse_header_bytes(data).unwrap_err();
+		assert!(matches!(parse(&[0xFF, 0xD8, 0xFF, 0xE0]), PngIcoError::BadSignature(0, 0xFF)));
+		assert!(matches!(parse(b"hello, world"), PngIcoError::BadSignature(0, b'h')));
+		// A PNG that went through a text-mode transfer, which turned its CRLF into LF.
+		let mangled = [&png[..4], &png[5..]].concat();
+		let err = parse(&mangled);
+		assert!(matches!(err, PngIcoError::BadSignature(4, 0x0A)));
+		assert_eq!(err.to_string(), "File is not a PNG: signature byte 4 is 0x0a, expected 0x0d.");
+		// Inputs shorter than the signature are still checked as far as they go.
+		assert!(matches!(parse(&[137, 80, 0]), PngIcoError::BadSignature(2, 0)));
+		assert!(is_png(&png) && !is_png(&png[..7]) && !is_png(&mangled));
+	}
+
+	#[test]
+	fn checks_only_the_signature_for_is_png() {
+		assert!(is_png(&PNG_SIGNATURE));
+		assert!(is_png(&rgba_png(1, 1, |_, _| [0; 4])));
+		assert!(!is_png(&PNG_SIGNATURE[..7]));
+		assert!(!is_png(&[]));
+		assert!(!is_png(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, b'J', b'F', b'I', b'F', 0]));
+	}
+
+	#[test]
+	fn finds_every_png_in_a_concatenated_blob() {
+		let (first, second) = (rgba_png(3, 2, |_, _| [0; 4]), rgba_png(5, 7, |_, _| [9; 4]));
+		let parser = PngParser::new();
+		let blob = [&first[..], &second[..]].concat();
+		let headers = parser.parse_all_headers(&blob).unwrap();
+		let found = headers.iter().map(|(offset, header)| (*offset, header.width, header.height)).collect::<Vec<_>>();
+		assert_eq!(found, [(0, 3, 2), (first.len(), 5, 7)]);
+		// Bytes between the PNGs are skipped.
+		let padded = [&b"junk"[..], &first, b"gap", &second].concat();
+		let offsets = parser.parse_all_headers(&padded).unwrap().into_iter().map(|(offset, _)| offset);
+		assert_eq!(offsets.collect::<Vec<_>>(), [4, 4 + first.len() + 3]);
+		assert!(parser.parse_all_headers(b"no images here").unwrap().is_empty());
+	}
+
+	#[test]
+	fn requires_ihdr_to_come_first() {
+		let png = rgba_png(1, 1, |_, _| [0; 4]);
+		let mut text_first = PNG_SIGNATURE.to_vec();
+		write_chunk(&mut text_first, b"tEXt", b"Title\0first");
+		text_first.extend_from_slice(&png[8..]);
+		let parser = PngParser::new();
+		assert!(matches!(parser.parse_header_bytes(&text_first), Err(PngIcoError::MissingHeader)));
+		assert!(matches!(parser.decode_bytes(&text_first), Err(PngIcoError::MissingHeader)));
+		assert!(matches!(parser.parse_header_from_reader(&text_first[..]), Err(PngIcoError::MissingHeader)));
+		assert!(matches!(parser.strict(true).decode_bytes(&text_first), Err(PngIcoError::MissingHeader)));
+	}
+
+	#[test]
+	fn strict_mode_rejects_what_lenient_mode_warns_about() {
+		let png = rgba_png(2, 2, |_, _| [1, 2, 3, 255]);
+		let iend = png.len() - 12;
+		let mut full_iend = png[..iend].to_vec();
+		write_chunk(&mut full_iend, b"IEND", b"data");
+		let cases = [
+			(full_iend, "IEND is not empty"),
+			(png[..iend].to_vec(), "there is no IEND chunk"),
+			([&png[..], b"trailing"].concat(), "data follows IEND"),
+		];
+		for (data, rule) in cases {
+			let decode = || assert_eq!(PngParser::new().decode_bytes(&data).unwrap().png_data, [1, 2, 3, 255].repeat(4));
+			#[cfg(feature = "log")]
+			assert_eq!(captured_warnings(decode), [PngIcoError::Nonconformant(rule).to_string()]);
+			#[cfg(not(feature = "log"))]
+			decode();
+			match PngParser::new().strict(true).decode_bytes(&data) {
+				Err(PngIcoError::Nonconformant(reason)) => assert_eq!(reason, rule),
+				other => panic!("expected {:?}, got {:?}", rule, other.map(|_| ())),
+			}
+		}
+		assert!(PngParser::new().strict(true).decode_bytes(&png).is_ok());
+	}
+
+	#[test]
+	fn reads_the_ihdr_length_from_the_chunk() {
+		// An IHDR with a stray fourteenth byte, so every later chunk sits one byte further on.
+		let png = rgba_png(1, 1, |_, _| [0; 4]);
+		let mut long = PNG_SIGNATURE.to_vec();
+		write_chunk(&mut long, b"IHDR", &[&png[16..29], &[0]].concat());
+		long.extend_from_slice(&png[33..]);
+		let parser = PngParser::new();
+		let err = parser.parse_header_bytes(&long).unwrap_err();
+		assert!(matches!(&err, PngIcoError::InvalidChunkLength(name) if name == "IHDR"));
+		assert!(matches!(parser.parse_header_from_reader(&long[..]), Err(PngIcoError::InvalidChunkLength(_))));
+		// The walker still finds the chunks after it.
+		let names = parser.chunks(&long).unwrap().map(|chunk| chunk.unwrap().name).collect::<Vec<_>>();
+		assert_eq!(names, [*b"IHDR", *b"IDAT", *b"IEND"]);
+	}
+
+	#[test]
+	fn counts_the_filter_type_of_each_scanline() {
+		let unfiltered = png_with_chunks((2, 3), 8, ColorType::Grayscale, &[], &[1, 2, 3, 4, 5, 6]);
+		assert_eq!(PngParser::new().decode_bytes(&unfiltered).unwrap().filter_type_counts(), [3, 0, 0, 0, 0]);
+
+		// A 1x6 grayscale image whose rows use None, Sub, Up, Average, Paeth and Up again.
+		let scanlines = [0, 10, 1, 20, 2, 30, 3, 40, 4, 50, 2, 60];
+		let mut header = [&1u32.to_be_bytes()[..], &6u32.to_be_bytes()].concat();
+		header.extend_from_slice(&[8, ColorType::Grayscale.to_u8(), 0, 0, 0]);
+		let mut png = PNG_SIGNATURE.to_vec();
+		write_chunk(&mut png, b"IHDR", &header);
+		write_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines, 6));
+		write_chunk(&mut png, b"IEND", &[]);
+		assert_eq!(PngParser::new().decode_bytes(&png).unwrap().filter_type_counts(), [1, 1, 2, 1, 1]);
+	}
+
+	#[test]
+	fn reads_from_any_kind_of_path() {
+		let dir = temp_dir("parser-paths");
+		let path: std::path::PathBuf = dir.join("source.png");
+		std::fs::write(&path, rgba_png(3, 2, |_, _| [0; 4])).unwrap();
+		let parser = PngParser::new();
+		assert_eq!(parser.parse_header(path.clone()).unwrap().width, 3);
+		assert_eq!(parser.parse_header(path.as_os_str()).unwrap().height, 2);
+		assert_eq!(parser.decode(&path).unwrap().metadata.width, 3);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	// Hands out at most one byte per read, and fails every other read with `Interrupted`, like a slow socket.
+	struct Trickle<'a> {
+		data: std::io::Cursor<&'a [u8]>,
+		interrupt: bool,
+	}
+
+	impl std::io::Read for Trickle<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			self.interrupt = !self.interrupt;
+			if self.interrupt {
+				return Err(std::io::ErrorKind::Interrupted.into());
+			}
+			let len = buf.len().min(1);
+			self.data.read(&mut buf[..len])
+		}
+	}
+
+	#[test]
+	fn reads_only_the_header_from_a_reader() {
+		let png = rgba_png(3, 2, |_, _| [0; 4]);
+		let mut reader = Trickle {
+			data: std::io::Cursor::new(&png[..]),
+			interrupt: false,
+		};
+		let metadata = PngParser::new().parse_header_from_reader(&mut reader).unwrap();
+		assert_eq!(metadata, PngParser::new().parse_header_bytes(&png).unwrap());
+		assert_eq!(reader.data.position(), MIN_PNG_SIZE as u64);
+
+		// It gives up as soon as the data can't be a PNG.
+		let mut cursor = std::io::Cursor::new(b"GIF89a and the rest of the file".to_vec());
+		let err = PngParser::new().parse_header_from_reader(&mut cursor).unwrap_err();
+		assert!(matches!(err, PngIcoError::BadSignature(0, b'G')));
+		assert_eq!(cursor.position(), 8);
+	}
+
+	// A 1x1 PNG whose header claims it is `width` by `height`, with a CRC to match.
+	fn claiming_size(width: u32, height: u32) -> Vec<u8> {
+		let mut png = rgba_png(1, 1, |_, _| [0, 0, 0, 255]);
+		png[16..20].copy_from_slice(&width.to_be_bytes());
+		png[20..24].copy_from_slice(&height.to_be_bytes());
+		let crc = crc32(&[&png[12..29]]);
+		png[29..33].copy_from_slice(&crc.to_be_bytes());
+		png
+	}
+
+	#[test]
+	fn rejects_image_data_that_inflates_past_the_declared_rows() {
+		let png = png_with_chunks((2, 1), 8, ColorType::Grayscale, &[], &[1, 2]);
+		assert_eq!(PngParser::new().decode_bytes(&png).unwrap().png_data, [1, 2]);
+		let extra_row = png_with_chunks((2, 1), 8, ColorType::Grayscale, &[], &[1, 2, 3, 4]);
+		let error = PngParser::new().decode_bytes(&extra_row);
+		assert!(matches!(error, Err(PngIcoError::Decompression("data inflates past its expected size"))));
+	}
+
+	#[test]
+	fn rejects_enormous_images_before_inflating_them() {
+		let png = claiming_size(100_000, 100_000);
+		let limit = 64 << 20;
+		match PngParser::new().memory_limit(limit).decode_bytes(&png) {
+			Err(PngIcoError::MemoryLimitExceeded(size, max)) => assert_eq!((size, max), (40_000_000_000, limit)),
+			other => panic!("expected MemoryLimitExceeded, got {:?}", other.map(|_| ())),
+		}
+		let default = PngParser::new().decode_bytes(&png);
+		assert!(matches!(default, Err(PngIcoError::MemoryLimitExceeded(_, DEFAULT_MEMORY_LIMIT))));
+	}
+
+	#[test]
+	fn checks_the_limit_against_the_decoded_size() {
+		let metadata = PngParser::new().parse_header_bytes(&claiming_size(100, 100)).unwrap();
+		assert_eq!(metadata.check_memory_limit(40_000).unwrap(), 40_000);
+		assert!(metadata.check_memory_limit(39_999).is_err());
+	}
+}
@@ -0,0 +1,99 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+// A distinct color of the image and the number of pixels that have it.
+type ColorCount = ([u8; 4], u32);
+
+// Fully transparent pixels all look the same, so they share a single color.
+fn normalize(pixel: &[u8]) -> [u8; 4] {
+	match pixel[3] {
+		0 => [0; 4],
+		_ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+	}
+}
+
+// The channel whose values are spread the widest across `colors`, and how widely.
+fn widest_channel(colors: &[ColorCount]) -> (usize, u8) {
+	(0..4)
+		.map(|channel| {
+			let values = colors.iter().map(|(color, _)| color[channel]);
+			let (min, max) = values.fold((u8::MAX, 0), |(min, max), value| (min.min(value), max.max(value)));
+			(channel, max.saturating_sub(min))
+		})
+		.max_by_key(|&(_, spread)| spread)
+		.unwrap_or((0, 0))
+}
+
+// The pixel-weighted average of `colors`, rounded to the nearest value.
+fn average(colors: &[ColorCount]) -> [u8; 4] {
+	let total = colors.iter().map(|&(_, count)| count as u64).sum::<u64>().max(1);
+	let mut average = [0; 4];
+	for (channel, value) in average.iter_mut().enumerate() {
+		let sum = colors.iter().map(|&(color, count)| color[channel] as u64 * count as u64).sum::<u64>();
+		*value = ((sum + total / 2) / total) as u8;
+	}
+	average
+}
+
+// Reduces 8-bit RGBA pixels to a palette of at most `max_colors` entries with median cut, and returns the palette
+// and each pixel's index into it. Images with few enough colors keep them exactly. The box with the widest channel is
+// split at its pixel-weighted median along that channel until there are enough boxes, and each box becomes the
+// average of its colors.
+pub(crate) fn median_cut(rgba: &[u8], max_colors: usize) -> (Vec<[u8; 4]>, Vec<u8>) {
+	let mut pixels = rgba.chunks(4).map(normalize).collect::<Vec<_>>();
+	pixels.sort_unstable();
+	let mut colors: Vec<ColorCount> = Vec::new();
+	for pixel in pixels {
+		match colors.last_mut() {
+			Some((color, count)) if *color == pixel => *count += 1,
+			_ => colors.push((pixel, 1)),
+		}
+	}
+
+	let mut boxes: Vec<Range<usize>> = Vec::new();
+	if !colors.is_empty() {
+		boxes.push(0..colors.len());
+	}
+	while boxes.len() < max_colors {
+		let widest = boxes
+			.iter()
+			.enumerate()
+			.map(|(index, range)| (index, widest_channel(&colors[range.clone()])))
+			.max_by_key(|&(_, (_, spread))| spread);
+		let (index, channel) = match widest {
+			Some((index, (channel, spread))) if spread > 0 => (index, channel),
+			// Every box holds a single color.
+			_ => break,
+		};
+		let range = boxes[index].clone();
+		let colors = &mut colors[range.clone()];
+		colors.sort_unstable_by_key(|(color, _)| color[channel]);
+		let half = colors.iter().map(|&(_, count)| count as u64).sum::<u64>() / 2;
+		let mut seen = 0;
+		let median = colors.iter().position(|&(_, count)| {
+			seen += count as u64;
+			seen > half
+		});
+		// Both halves must keep at least one color, and the channel spread guarantees there are two.
+		let split = range.start + median.unwrap_or(0).clamp(1, colors.len() - 1);
+		boxes[index] = range.start..split;
+		boxes.push(split..range.end);
+	}
+
+	let palette = boxes.iter().map(|range| average(&colors[range.clone()])).collect::<Vec<_>>();
+	let mut lookup = Vec::with_capacity(colors.len());
+	for (index, range) in boxes.iter().enumerate() {
+		lookup.extend(colors[range.clone()].iter().map(|&(color, _)| (color, index as u8)));
+	}
+	lookup.sort_unstable();
+	let indices = rgba
+		.chunks(4)
+		.map(|pixel| {
+			let color = normalize(pixel);
+			// Every pixel's color was counted above, so the search always finds it.
+			let found = lookup.binary_search_by_key(&color, |&(color, _)| color).unwrap_or(0);
+			lookup[found].1
+		})
+		.collect();
+	(palette, indices)
+}
@@ -0,0 +1,76 @@
+use alloc::vec::Vec;
+
+use super::palette::read_sample;
+use super::{ColorType, EncodedPng, Transparency};
+
+impl EncodedPng {
+	// Decoding has already rejected unknown color types.
+	fn color(&self) -> ColorType {
+		self.metadata.color().unwrap_or(ColorType::TruecolorAlpha)
+	}
+
+	// Indexed images have already been expanded to 8-bit RGBA during decoding.
+	fn stored_bits_per_pixel(&self) -> usize {
+		match self.color() {
+			ColorType::Indexed => 32,
+			color => (color.channel_count() * self.metadata.bit_depth as u32) as usize,
+		}
+	}
+
+	/// The number of bytes each pixel takes up in `png_data`, rounded up to 1 for bit depths below 8.
+	pub fn bytes_per_pixel(&self) -> usize {
+		self.stored_bits_per_pixel().div_ceil(8)
+	}
+
+	/// The number of bytes between the starts of consecutive rows in `png_data`.
+	pub fn row_stride(&self) -> usize {
+		(self.metadata.width as usize * self.stored_bits_per_pixel()).div_ceil(8)
+	}
+
+	/// The pixel at `x`, `y` converted to 8-bit RGBA, applying any tRNS transparency.
+	pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+		let color = self.color();
+		let start = y as usize * self.row_stride();
+		let row = &self.png_data[start..start + self.row_stride()];
+		let x = x as usize;
+		if color == ColorType::Indexed {
+			return [row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]];
+		}
+
+		let bit_depth = self.metadata.bit_depth;
+		let channels = color.channel_count() as usize;
+		let max = (1u32 << bit_depth) - 1;
+		let scale = |sample: u16| (sample as u32 * 255 / max) as u8;
+		let sample = |channel: usize| read_sample(row, x * channels + channel, bit_depth);
+		let (rgb, alpha) = match color {
+			ColorType::Grayscale => {
+				let gray = sample(0);
+				let transparent = self.info.transparency == Some(Transparency::Gray(gray));
+				([gray; 3], if transparent { 0 } else { 255 })
+			}
+			ColorType::Truecolor => {
+				let (r, g, b) = (sample(0), sample(1), sample(2));
+				let transparent = self.info.transparency == Some(Transparency::Rgb(r, g, b));
+				([r, g, b], if transparent { 0 } else { 255 })
+			}
+			ColorType::GrayscaleAlpha => ([sample(0); 3], scale(sample(1))),
+			_ => ([sample(0), sample(1), sample(2)], scale(sample(3))),
+		};
+		[scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), alpha]
+	}
+
+	/// Every pixel as `(x, y, rgba)`, row by row from the top-left.
+	pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, [u8; 4])> + '_ {
+		let (width, height) = (self.metadata.width, self.metadata.height);
+		(0..height).flat_map(move |y| (0..width).map(move |x| (x, y, self.pixel(x, y))))
+	}
+
+	// Converts the decoded pixels of any color type and bit depth to 8-bit RGBA.
+	pub(crate) fn to_rgba8(&self) -> Vec<u8> {
+		let mut out = Vec::with_capacity(self.metadata.width as usize * self.metadata.height as usize * 4);
+		for (_, _, rgba) in self.pixels() {
+			out.extend_from_slice(&rgba);
+		}
+		out
+	}
+}
@@ -0,0 +1,126 @@
+// Fixtures shared by the unit tests: scratch directories, generated PNGs and, with the `log` feature, a logger that
+// records the warnings each test thread emits.
+
+use std::path::PathBuf;
+
+use super::crc::crc32;
+use super::ColorType;
+
+// An empty directory for one test, named after it so tests running at the same time don't share files.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("iconifier-{}-{}", std::process::id(), name));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+pub(crate) fn write_chunk(out: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+	out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	out.extend_from_slice(name);
+	out.extend_from_slice(data);
+	out.extend_from_slice(&crc32(&[name, data]).to_be_bytes());
+}
+
+// Wraps `data` in a zlib stream of stored DEFLATE blocks, which any inflater reads without compression support.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+	let mut out = vec![0x78, 0x01];
+	let mut blocks = data.chunks(0xFFFF).peekable();
+	if blocks.peek().is_none() {
+		out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+	}
+	while let Some(block) = blocks.next() {
+		out.push(blocks.peek().is_none() as u8);
+		out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+		out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+		out.extend_from_slice(block);
+	}
+	let (mut a, mut b) = (1u32, 0u32);
+	for &byte in data {
+		a = (a + byte as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+	out
+}
+
+// An 8-bit RGBA PNG whose pixels are given by `pixel(x, y)`.
+pub(crate) fn rgba_png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+	let mut scanlines = Vec::new();
+	for y in 0..height {
+		scanlines.push(0);
+		(0..width).for_each(|x| scanlines.extend_from_slice(&pixel(x, y)));
+	}
+	let mut header = [&width.to_be_bytes()[..], &height.to_be_bytes()].concat();
+	header.extend_from_slice(&[8, 6, 0, 0, 0]);
+	let mut out = vec![137, 80, 78, 71, 13, 10, 26, 10];
+	write_chunk(&mut out, b"IHDR", &header);
+	write_chunk(&mut out, b"IDAT", &zlib_stored(&scanlines));
+	write_chunk(&mut out, b"IEND", &[]);
+	out
+}
+
+// A non-interlaced PNG of `color` at `bit_depth` whose `rows` of packed samples are stored unfiltered, with `chunks`
+// between IHDR and IDAT.
+pub(crate) fn png_with_chunks(
+	(width, height): (u32, u32),
+	bit_depth: u8,
+	color: ColorType,
+	chunks: &[(&[u8; 4], &[u8])],
+	rows: &[u8],
+) -> Vec<u8> {
+	let mut header = [&width.to_be_bytes()[..], &height.to_be_bytes()].concat();
+	header.extend_from_slice(&[bit_depth, color.to_u8(), 0, 0, 0]);
+	let mut out = vec![137, 80, 78, 71, 13, 10, 26, 10];
+	write_chunk(&mut out, b"IHDR", &header);
+	for (name, data) in chunks {
+		write_chunk(&mut out, name, data);
+	}
+	let row_len = (width as usize * (color.channel_count() * bit_depth as u32) as usize).div_ceil(8);
+	let scanlines = rows.chunks(row_len).flat_map(|row| [&[0][..], row].concat()).collect::<Vec<_>>();
+	write_chunk(&mut out, b"IDAT", &zlib_stored(&scanlines));
+	write_chunk(&mut out, b"IEND", &[]);
+	out
+}
+
+#[cfg(feature = "log")]
+pub(crate) use capture::captured_warnings;
+
+#[cfg(feature = "log")]
+mod capture {
+	use std::sync::{Mutex, Once};
+	use std::thread::{self, ThreadId};
+
+	struct Capture(Mutex<Vec<(ThreadId, String)>>);
+
+	impl log::Log for Capture {
+		fn enabled(&self, metadata: &log::Metadata) -> bool {
+			metadata.level() <= log::Level::Warn
+		}
+
+		fn log(&self, record: &log::Record) {
+			if self.enabled(record.metadata()) {
+				self.0.lock().unwrap().push((thread::current().id(), record.args().to_string()));
+			}
+		}
+
+		fn flush(&self) {}
+	}
+
+	static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+	static INSTALL: Once = Once::new();
+
+	// The warnings logged on this thread while `run` runs. Tests run in parallel, so other threads' are left out.
+	pub(crate) fn captured_warnings(run: impl FnOnce()) -> Vec<String> {
+		INSTALL.call_once(|| {
+			log::set_logger(&LOGGER).unwrap();
+			log::set_max_level(log::LevelFilter::Warn);
+		});
+		let id = thread::current().id();
+		LOGGER.0.lock().unwrap().retain(|(thread, _)| *thread != id);
+		run();
+		let mut messages = LOGGER.0.lock().unwrap();
+		let (mine, others): (Vec<_>, Vec<_>) = messages.drain(..).partition(|(thread, _)| *thread == id);
+		*messages = others;
+		mine.into_iter().map(|(_, message)| message).collect()
+	}
+}
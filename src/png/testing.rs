@@ -0,0 +1,251 @@
+// Helpers for testing against the PNG decoder, for this crate and for others. `assert_round_trip` checks that an
+// image survives being decoded, re-encoded and decoded again, and `sample_pngs` provides an image for every color
+// type and bit depth to run it on. `rgba_png` and `insert_chunk` build custom inputs.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use super::deflate::zlib_compress;
+use super::encoder::{encode_png, write_chunk, DEFAULT_COMPRESSION_LEVEL};
+use super::interlace::ADAM7_PASSES;
+use super::{ColorType, PngParser, PNG_SIGNATURE};
+use crate::Result;
+
+// Odd, so sub-byte rows end mid-byte and every Adam7 pass is partly filled.
+const SAMPLE_WIDTH: u32 = 13;
+const SAMPLE_HEIGHT: u32 = 11;
+
+/// Where two RGBA buffers first differ, and how many of their pixels differ in total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelDiff {
+	pub x: u32,
+	pub y: u32,
+	pub expected: [u8; 4],
+	pub actual: [u8; 4],
+	pub count: usize,
+}
+
+impl fmt::Display for PixelDiff {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} pixels differ, first at ({}, {}): expected {:?}, got {:?}",
+			self.count, self.x, self.y, self.expected, self.actual
+		)
+	}
+}
+
+/// Compares two 8-bit RGBA buffers of an image `width` pixels wide. Returns None if they are identical.
+///
+/// # Panics
+///
+/// If the buffers differ in length.
+pub fn pixel_diff(expected: &[u8], actual: &[u8], width: u32) -> Option<PixelDiff> {
+	assert_eq!(expected.len(), actual.len(), "the images differ in size");
+	let mut diff: Option<PixelDiff> = None;
+	for (index, (expected, actual)) in expected.chunks(4).zip(actual.chunks(4)).enumerate() {
+		if expected == actual {
+			continue;
+		}
+		match &mut diff {
+			Some(diff) => diff.count += 1,
+			None => {
+				let pixel = |rgba: &[u8]| [rgba[0], rgba[1], rgba[2], rgba[3]];
+				diff = Some(PixelDiff {
+					x: (index % width.max(1) as usize) as u32,
+					y: (index / width.max(1) as usize) as u32,
+					expected: pixel(expected),
+					actual: pixel(actual),
+					count: 1,
+				});
+			}
+		}
+	}
+	diff
+}
+
+/// Decodes a PNG to its width, height and 8-bit RGBA pixels.
+pub fn decode_rgba(png: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+	let decoded = PngParser::new().decode_bytes(png)?;
+	Ok((decoded.metadata.width, decoded.metadata.height, decoded.to_rgba8()))
+}
+
+/// Decodes `png`, re-encodes the pixels as an RGBA PNG and decodes that, asserting that the pixels are unchanged.
+///
+/// # Panics
+///
+/// If either decode fails or any pixel differs, with the first difference in the message.
+pub fn assert_round_trip(png: &[u8]) {
+	let (width, height, rgba) = decode_rgba(png).unwrap_or_else(|err| panic!("could not decode the image: {}", err));
+	let reencoded = encode_png(&rgba, width, height, None, DEFAULT_COMPRESSION_LEVEL);
+	let (_, _, round_trip) =
+		decode_rgba(&reencoded).unwrap_or_else(|err| panic!("could not decode the re-encoded image: {}", err));
+	if let Some(diff) = pixel_diff(&rgba, &round_trip, width) {
+		panic!("round trip changed the image: {}", diff);
+	}
+}
+
+/// An 8-bit RGBA PNG of `width` by `height` pixels, whose colors are given by `pixel(x, y)`.
+pub fn rgba_png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+	let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+	for y in 0..height {
+		(0..width).for_each(|x| rgba.extend_from_slice(&pixel(x, y)));
+	}
+	encode_png(&rgba, width, height, None, DEFAULT_COMPRESSION_LEVEL)
+}
+
+/// A copy of `png` with a chunk of type `name` holding `data` right before its IEND chunk.
+///
+/// # Panics
+///
+/// If `png` doesn't end with IEND.
+pub fn insert_chunk(png: &[u8], name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+	let iend = png.len().checked_sub(12).filter(|&iend| &png[iend + 4..iend + 8] == b"IEND");
+	let iend = iend.expect("the PNG ends with IEND");
+	let mut out = png[..iend].to_vec();
+	write_chunk(&mut out, name, data);
+	out.extend_from_slice(&png[iend..]);
+	out
+}
+
+/// A small non-interlaced PNG for every valid combination of color type and bit depth, plus interlaced copies of
+/// the 8-bit ones, each with a name such as "indexed 4-bit". Grayscale, truecolor and indexed samples have tRNS
+/// transparency, and the pixels vary across every sample value the bit depth allows.
+pub fn sample_pngs() -> Vec<(String, Vec<u8>)> {
+	let colors = [
+		ColorType::Grayscale,
+		ColorType::Truecolor,
+		ColorType::Indexed,
+		ColorType::GrayscaleAlpha,
+		ColorType::TruecolorAlpha,
+	];
+	let mut samples = Vec::new();
+	for color in colors {
+		for &bit_depth in color.allowed_bit_depths() {
+			samples.push((format!("{} {}-bit", color.name(), bit_depth), sample_png(color, bit_depth, false)));
+		}
+		samples.push((format!("{} 8-bit interlaced", color.name()), sample_png(color, 8, true)));
+	}
+	samples
+}
+
+// A sample value that varies with the position and channel, for a `bit_depth` bit sample.
+fn pattern(x: u32, y: u32, channel: u32, bit_depth: u8) -> u16 {
+	let value = (x * 7 + y * 31 + channel * 59).wrapping_mul(2_654_435_761) >> 7;
+	(value & ((1 << bit_depth) - 1)) as u16
+}
+
+// An unfiltered row of the pixels at `xs` on row `y`, packed most significant bits first.
+fn sample_row(xs: impl Iterator<Item = u32>, y: u32, channels: u32, bit_depth: u8) -> Vec<u8> {
+	let mut row = vec![0];
+	let mut bits = 0;
+	for x in xs {
+		for channel in 0..channels {
+			let sample = pattern(x, y, channel, bit_depth);
+			if bit_depth == 16 {
+				row.extend_from_slice(&sample.to_be_bytes());
+			} else if bit_depth == 8 {
+				row.push(sample as u8);
+			} else {
+				if bits % 8 == 0 {
+					row.push(0);
+				}
+				*row.last_mut().unwrap() |= (sample as u8) << (8 - bit_depth as u32 - bits % 8);
+				bits += bit_depth as u32;
+			}
+		}
+	}
+	row
+}
+
+fn sample_png(color: ColorType, bit_depth: u8, interlaced: bool) -> Vec<u8> {
+	let (width, height) = (SAMPLE_WIDTH, SAMPLE_HEIGHT);
+	let mut header = Vec::with_capacity(13);
+	header.extend_from_slice(&width.to_be_bytes());
+	header.extend_from_slice(&height.to_be_bytes());
+	header.extend_from_slice(&[bit_depth, color.to_u8(), 0, 0, interlaced as u8]);
+
+	let mut out = PNG_SIGNATURE.to_vec();
+	write_chunk(&mut out, b"IHDR", &header);
+	match color {
+		ColorType::Indexed => {
+			let entries = 1u32 << bit_depth;
+			let palette = (0..entries).flat_map(|index| (0..3).map(move |channel| pattern(index, 0, channel, 8) as u8));
+			write_chunk(&mut out, b"PLTE", &palette.collect::<Vec<_>>());
+			// Only the first half of the palette gets alpha values, leaving the rest opaque.
+			let alpha = (0..entries.div_ceil(2)).map(|index| pattern(index, 1, 0, 8) as u8);
+			write_chunk(&mut out, b"tRNS", &alpha.collect::<Vec<_>>());
+		}
+		ColorType::Grayscale | ColorType::Truecolor => {
+			let channels = color.channel_count();
+			let key = (0..channels).flat_map(|channel| pattern(1, 1, channel, bit_depth).to_be_bytes());
+			write_chunk(&mut out, b"tRNS", &key.collect::<Vec<_>>());
+		}
+		_ => {}
+	}
+
+	// Indexed images have a single index per pixel, which selects a palette entry.
+	let channels = color.channel_count();
+	let mut raw = Vec::new();
+	if interlaced {
+		for &(x_offset, y_offset, x_step, y_step) in &ADAM7_PASSES {
+			if x_offset >= width {
+				continue;
+			}
+			for y in (y_offset..height).step_by(y_step as usize) {
+				raw.extend(sample_row((x_offset..width).step_by(x_step as usize), y, channels, bit_depth));
+			}
+		}
+	} else {
+		for y in 0..height {
+			raw.extend(sample_row(0..width, y, channels, bit_depth));
+		}
+	}
+	write_chunk(&mut out, b"IDAT", &zlib_compress(&raw, DEFAULT_COMPRESSION_LEVEL));
+	write_chunk(&mut out, b"IEND", &[]);
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_sample_round_trips() {
+		let samples = sample_pngs();
+		// Five bit depths of grayscale, two each of truecolor, grayscale with alpha and RGBA, four of indexed, and an
+		// interlaced copy of each color type.
+		assert_eq!(samples.len(), 15 + 5);
+		for (name, png) in samples {
+			let (width, height, _) = decode_rgba(&png).unwrap_or_else(|err| panic!("{}: {}", name, err));
+			assert_eq!((width, height), (SAMPLE_WIDTH, SAMPLE_HEIGHT), "{}", name);
+			assert_round_trip(&png);
+		}
+	}
+
+	#[test]
+	fn builds_rgba_pngs_with_extra_chunks() {
+		let png = rgba_png(3, 2, |x, y| [x as u8, y as u8, 7, 255]);
+		let (width, height, rgba) = decode_rgba(&png).unwrap();
+		assert_eq!((width, height), (3, 2));
+		assert_eq!(rgba[4 * 5..], [2, 1, 7, 255]);
+
+		let padded = insert_chunk(&png, b"prVt", &[0; 100]);
+		assert_eq!(padded.len(), png.len() + 112);
+		assert_eq!(decode_rgba(&padded).unwrap().2, rgba);
+	}
+
+	#[test]
+	fn reports_the_first_differing_pixel() {
+		let expected = [0u8; 4 * 6];
+		let mut actual = expected;
+		actual[4 * 4 + 1] = 1;
+		actual[4 * 5 + 3] = 1;
+		let diff = pixel_diff(&expected, &actual, 3).unwrap();
+		assert_eq!((diff.x, diff.y, diff.count), (1, 1, 2));
+		assert_eq!(diff.actual, [0, 1, 0, 0]);
+		assert!(pixel_diff(&expected, &expected, 3).is_none());
+	}
+}
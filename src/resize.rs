@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+
+use crate::{PngIcoError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+	/// Picks the closest source pixel. Fastest, but aliases badly when downscaling.
+	Nearest,
+	/// Linear interpolation, widened to average every covered pixel when downscaling.
+	Bilinear,
+	/// Windowed sinc over three lobes. Slowest, and the sharpest for small icon sizes.
+	#[default]
+	Lanczos3,
+}
+
+impl ResizeFilter {
+	fn support(self) -> f32 {
+		match self {
+			ResizeFilter::Nearest => 0.5,
+			ResizeFilter::Bilinear => 1.0,
+			ResizeFilter::Lanczos3 => 3.0,
+		}
+	}
+
+	fn kernel(self, x: f32) -> f32 {
+		let x = x.abs();
+		match self {
+			ResizeFilter::Nearest => 1.0,
+			ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+			ResizeFilter::Lanczos3 => {
+				if x == 0.0 {
+					1.0
+				} else if x < 3.0 {
+					let x = x * PI;
+					3.0 * x.sin() * (x / 3.0).sin() / (x * x)
+				} else {
+					0.0
+				}
+			}
+		}
+	}
+}
+
+// Filter weights for every output sample along one axis, as the first source index and the weight of each tap.
+fn weights(src_len: u32, dst_len: u32, filter: ResizeFilter) -> Vec<(usize, Vec<f32>)> {
+	let scale = src_len as f32 / dst_len as f32;
+	(0..dst_len)
+		.map(|dst| {
+			let center = (dst as f32 + 0.5) * scale;
+			if filter == ResizeFilter::Nearest {
+				return ((center as usize).min(src_len as usize - 1), vec![1.0]);
+			}
+
+			// Stretch the kernel when downscaling so every source pixel contributes.
+			let stretch = scale.max(1.0);
+			let support = filter.support() * stretch;
+			let start = (center - support).floor().max(0.0) as usize;
+			let end = ((center + support).ceil() as usize).min(src_len as usize);
+			let mut taps = (start..end)
+				.map(|src| filter.kernel((src as f32 + 0.5 - center) / stretch))
+				.collect::<Vec<_>>();
+			let total: f32 = taps.iter().sum();
+			if total != 0.0 {
+				taps.iter_mut().for_each(|tap| *tap /= total);
+			}
+			(start, taps)
+		})
+		.collect()
+}
+
+// The sRGB transfer function and its inverse, on values from 0 to 255.
+fn srgb_to_linear(value: f32) -> f32 {
+	let value = value / 255.0;
+	let linear = if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) };
+	linear * 255.0
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+	let value = value / 255.0;
+	let srgb = if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+	srgb * 255.0
+}
+
+/// Resizes an 8-bit sRGB RGBA buffer. Color is weighted by alpha so transparent pixels don't bleed into their
+/// neighbours, and filtered in linear light so that downscaled edges and fine detail don't come out too dark.
+pub fn resize(
+	rgba: &[u8],
+	src_w: u32,
+	src_h: u32,
+	dst_w: u32,
+	dst_h: u32,
+	filter: ResizeFilter,
+) -> Result<Vec<u8>> {
+	resize_with(rgba, src_w, src_h, dst_w, dst_h, filter, true)
+}
+
+/// Like [`resize`], but filters the sRGB values directly unless `linear_light` is set. That is faster, but averages
+/// e.g. a black and white checkerboard to a gray that looks darker than the original.
+pub fn resize_with(
+	rgba: &[u8],
+	src_w: u32,
+	src_h: u32,
+	dst_w: u32,
+	dst_h: u32,
+	filter: ResizeFilter,
+	linear_light: bool,
+) -> Result<Vec<u8>> {
+	if rgba.len() != src_w as usize * src_h as usize * 4 || src_w == 0 || src_h == 0 {
+		return Err(PngIcoError::InvalidImageData("buffer length does not match the given dimensions"));
+	}
+	if (src_w, src_h) == (dst_w, dst_h) {
+		return Ok(rgba.to_vec());
+	}
+
+	// Alpha is linear already, so only the color channels go through the table.
+	let decode = (0..=255)
+		.map(|value| if linear_light { srgb_to_linear(value as f32) } else { value as f32 })
+		.collect::<Vec<_>>();
+	let premultiplied = rgba
+		.chunks(4)
+		.flat_map(|px| {
+			let alpha = px[3] as f32 / 255.0;
+			let color = |c: u8| decode[c as usize] * alpha;
+			[color(px[0]), color(px[1]), color(px[2]), px[3] as f32]
+		})
+		.collect::<Vec<_>>();
+
+	let horizontal = weights(src_w, dst_w, filter);
+	let mut rows = vec![0f32; dst_w as usize * src_h as usize * 4];
+	for y in 0..src_h as usize {
+		for (x, (start, taps)) in horizontal.iter().enumerate() {
+			let out = &mut rows[(y * dst_w as usize + x) * 4..][..4];
+			for (i, tap) in taps.iter().enumerate() {
+				let px = &premultiplied[(y * src_w as usize + start + i) * 4..][..4];
+				out.iter_mut().zip(px).for_each(|(out, px)| *out += px * tap);
+			}
+		}
+	}
+
+	let vertical = weights(src_h, dst_h, filter);
+	let mut out = Vec::with_capacity(dst_w as usize * dst_h as usize * 4);
+	for (start, taps) in &vertical {
+		for x in 0..dst_w as usize {
+			let mut px = [0f32; 4];
+			for (i, tap) in taps.iter().enumerate() {
+				let src = &rows[((start + i) * dst_w as usize + x) * 4..][..4];
+				px.iter_mut().zip(src).for_each(|(px, src)| *px += src * tap);
+			}
+			// Lanczos can ring past the valid range, so clamp before undoing the premultiplication.
+			let alpha = px[3].clamp(0.0, 255.0);
+			let unpremultiply = if alpha > 0.0 { 255.0 / alpha } else { 0.0 };
+			out.extend(px[..3].iter().map(|c| {
+				let c = (c * unpremultiply).clamp(0.0, 255.0);
+				let c = if linear_light { linear_to_srgb(c) } else { c };
+				c.round().clamp(0.0, 255.0) as u8
+			}));
+			out.push(alpha.round() as u8);
+		}
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FILTERS: [ResizeFilter; 3] = [ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Lanczos3];
+
+	// Opaque black and white 1px squares.
+	fn checkerboard(size: u32) -> Vec<u8> {
+		(0..size * size)
+			.flat_map(|i| if (i % size + i / size).is_multiple_of(2) { [0, 0, 0, 255] } else { [255; 4] })
+			.collect()
+	}
+
+	fn grays(rgba: &[u8]) -> Vec<u8> {
+		rgba.chunks(4).map(|px| px[0]).collect()
+	}
+
+	#[test]
+	fn downscales_checkerboards() {
+		let board = checkerboard(16);
+		for filter in FILTERS {
+			let out = resize_with(&board, 16, 16, 4, 4, filter, false).unwrap();
+			assert!(out.chunks(4).all(|px| px[0] == px[1] && px[1] == px[2] && px[3] == 255), "{:?}", filter);
+		}
+		// Every sampled center falls on a black square, so Nearest aliases the board to black.
+		assert_eq!(grays(&resize(&board, 16, 16, 4, 4, ResizeFilter::Nearest).unwrap()), [0; 16]);
+		for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+			let srgb = grays(&resize_with(&board, 16, 16, 4, 4, filter, false).unwrap());
+			assert!(srgb.iter().all(|gray| (127..=128).contains(gray)), "{:?}: {:?}", filter, srgb);
+			// Half the light of white is brighter than the sRGB midpoint.
+			let linear = grays(&resize(&board, 16, 16, 4, 4, filter).unwrap());
+			assert!(linear.iter().all(|gray| (187..=188).contains(gray)), "{:?}: {:?}", filter, linear);
+		}
+	}
+
+	#[test]
+	fn lanczos_keeps_edges_sharper_than_bilinear() {
+		// A black left half and a white right half, downscaled 4x.
+		let edge = (0..32 * 8).flat_map(|i| if i % 32 < 16 { [0, 0, 0, 255] } else { [255; 4] }).collect::<Vec<_>>();
+		let row = |filter| grays(&resize_with(&edge, 32, 8, 8, 2, filter, false).unwrap())[..8].to_vec();
+		let (bilinear, lanczos) = (row(ResizeFilter::Bilinear), row(ResizeFilter::Lanczos3));
+		assert_eq!((bilinear[0], bilinear[7], lanczos[0], lanczos[7]), (0, 255, 0, 255));
+		// The two pixels either side of the edge are further apart with Lanczos.
+		assert!(lanczos[4] - lanczos[3] > bilinear[4] - bilinear[3], "{:?} {:?}", bilinear, lanczos);
+	}
+
+	#[test]
+	fn transparent_pixels_do_not_bleed() {
+		// Transparent red next to opaque blue stays blue where they're averaged.
+		let rgba = [[255, 0, 0, 0], [0, 0, 255, 255]].repeat(2).concat();
+		for filter in FILTERS {
+			let out = resize(&rgba, 2, 2, 1, 1, filter).unwrap();
+			assert_eq!(out[..3], [0, 0, 255], "{:?}", filter);
+		}
+		assert_eq!(resize(&rgba, 2, 2, 1, 1, ResizeFilter::Bilinear).unwrap()[3], 128);
+	}
+
+	#[test]
+	fn checks_the_buffer_size() {
+		let board = checkerboard(4);
+		assert_eq!(resize(&board, 4, 4, 4, 4, ResizeFilter::Lanczos3).unwrap(), board);
+		assert!(matches!(resize(&board, 4, 3, 2, 2, ResizeFilter::Nearest), Err(PngIcoError::InvalidImageData(_))));
+		assert!(resize(&[], 0, 0, 2, 2, ResizeFilter::Nearest).is_err());
+	}
+}
@@ -0,0 +1,156 @@
+use alloc::vec::Vec;
+
+use crate::png::check_rgba_size;
+use crate::{PngIcoError, Result};
+
+const HEADER_SIZE: usize = 18;
+const UNCOMPRESSED_TRUECOLOR: u8 = 2;
+const RLE_TRUECOLOR: u8 = 10;
+// Descriptor bits saying that the first stored pixel is on the right, and that the first stored row is the top one.
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+fn invalid(reason: &'static str) -> PngIcoError {
+	PngIcoError::InvalidTga(reason)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+	u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+// Expands run-length packets into `pixels` pixels of `bytes_per_pixel` bytes each. Each packet starts with a byte
+// whose low 7 bits are one less than its pixel count: with the high bit set, a single pixel follows to be repeated,
+// and otherwise that many pixels follow as they are. Packets may run across rows.
+fn decompress(data: &[u8], pixels: usize, bytes_per_pixel: usize) -> Result<Vec<u8>> {
+	let len = pixels * bytes_per_pixel;
+	// The image size comes from the file, so the output grows with the data actually decoded instead.
+	let mut out = Vec::new();
+	let mut pos = 0;
+	while out.len() < len {
+		let packet = *data.get(pos).ok_or(invalid("image data ends early"))?;
+		let count = (packet & 0x7F) as usize + 1;
+		pos += 1;
+		if packet & 0x80 != 0 {
+			let pixel = data.get(pos..pos + bytes_per_pixel).ok_or(invalid("image data ends early"))?;
+			for _ in 0..count {
+				out.extend_from_slice(pixel);
+			}
+			pos += bytes_per_pixel;
+		} else {
+			let run = data.get(pos..pos + count * bytes_per_pixel).ok_or(invalid("image data ends early"))?;
+			out.extend_from_slice(run);
+			pos += run.len();
+		}
+	}
+	out.truncate(len);
+	Ok(out)
+}
+
+// Decodes an uncompressed or run-length encoded 24 or 32-bit truecolor TGA into 8-bit RGBA rows, top row first.
+// 32-bit images are only transparent if the descriptor says their fourth byte is alpha; otherwise they're opaque.
+pub(crate) fn decode_tga(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+	if data.len() < HEADER_SIZE {
+		return Err(invalid("file is too short to contain a header"));
+	}
+	let image_type = data[2];
+	if image_type != UNCOMPRESSED_TRUECOLOR && image_type != RLE_TRUECOLOR {
+		return Err(invalid("only truecolor images are supported"));
+	}
+	let width = read_u16(data, 12) as usize;
+	let height = read_u16(data, 14) as usize;
+	if width == 0 || height == 0 {
+		return Err(invalid("image has no pixels"));
+	}
+	check_rgba_size(width as u32, height as u32)?;
+	let bytes_per_pixel = match data[16] {
+		24 => 3,
+		32 => 4,
+		_ => return Err(invalid("only 24 and 32-bit images are supported")),
+	};
+	let descriptor = data[17];
+	let has_alpha = bytes_per_pixel == 4 && descriptor & 0x0F != 0;
+
+	// The image ID and any color map, which truecolor images don't use, come before the pixels.
+	let color_map_len = match data[1] {
+		0 => 0,
+		_ => read_u16(data, 5) as usize * (data[7] as usize).div_ceil(8),
+	};
+	let start = HEADER_SIZE + data[0] as usize + color_map_len;
+	let stored = data.get(start..).ok_or(invalid("color map runs past the end of the file"))?;
+	let pixels = width * height;
+	let stored = if image_type == RLE_TRUECOLOR {
+		decompress(stored, pixels, bytes_per_pixel)?
+	} else {
+		stored
+			.get(..pixels * bytes_per_pixel)
+			.ok_or(invalid("image data ends early"))?
+			.to_vec()
+	};
+
+	let mut rgba = Vec::with_capacity(pixels * 4);
+	for y in 0..height {
+		// Rows are stored bottom to top unless the descriptor says otherwise.
+		let row = if descriptor & TOP_TO_BOTTOM != 0 { y } else { height - 1 - y };
+		for x in 0..width {
+			let column = if descriptor & RIGHT_TO_LEFT != 0 { width - 1 - x } else { x };
+			let pixel = &stored[(row * width + column) * bytes_per_pixel..][..bytes_per_pixel];
+			let alpha = if has_alpha { pixel[3] } else { 255 };
+			rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], alpha]);
+		}
+	}
+	Ok((width as u32, height as u32, rgba))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::png::test_support::temp_dir;
+	use crate::png::testing::decode_rgba;
+	use crate::png::{parse_ico, ConversionBuilder};
+
+	// A 2x2 32-bit RLE TGA with 8 alpha bits: a run of two red pixels, then green and half-transparent blue as they
+	// are. Stored bottom row first unless `descriptor` says otherwise.
+	fn rle_tga(descriptor: u8) -> Vec<u8> {
+		let mut tga = vec![0u8; HEADER_SIZE];
+		tga[2] = RLE_TRUECOLOR;
+		tga[12..16].copy_from_slice(&[2, 0, 2, 0]);
+		tga[16] = 32;
+		tga[17] = 8 | descriptor;
+		tga.extend_from_slice(&[0x81, 0, 0, 255, 255]);
+		tga.extend_from_slice(&[0x01, 0, 255, 0, 255, 255, 0, 0, 128]);
+		tga
+	}
+
+	#[test]
+	fn rejects_images_over_the_memory_limit() {
+		let mut header = [0u8; HEADER_SIZE];
+		header[2] = RLE_TRUECOLOR;
+		header[12..16].copy_from_slice(&[0xFF; 4]);
+		header[16] = 32;
+		assert!(matches!(decode_tga(&header), Err(PngIcoError::MemoryLimitExceeded(..))));
+	}
+
+	#[test]
+	fn decodes_rle_images_in_either_orientation() {
+		let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 128]);
+		assert_eq!(decode_tga(&rle_tga(0)).unwrap(), (2, 2, [green, blue, red, red].concat()));
+		let flipped = decode_tga(&rle_tga(TOP_TO_BOTTOM | RIGHT_TO_LEFT)).unwrap();
+		assert_eq!(flipped, (2, 2, [red, red, blue, green].concat()));
+		// Without alpha bits in the descriptor, the fourth byte is ignored.
+		let mut opaque = rle_tga(0);
+		opaque[17] = 0;
+		assert_eq!(decode_tga(&opaque).unwrap().2[4..8], [0, 0, 255, 255]);
+		assert!(matches!(decode_tga(&rle_tga(0)[..HEADER_SIZE + 7]), Err(PngIcoError::InvalidTga(_))));
+	}
+
+	#[test]
+	fn converts_rle_images_to_icons() {
+		let dir = temp_dir("tga-to-ico");
+		std::fs::write(dir.join("sprite.tga"), rle_tga(0)).unwrap();
+		ConversionBuilder::new().convert(dir.join("sprite.tga"), dir.join("sprite.ico")).unwrap();
+		let entries = parse_ico(dir.join("sprite.ico")).unwrap();
+		assert_eq!((entries.len(), entries[0].width, entries[0].height), (1, 2, 2));
+		assert_eq!(decode_rgba(&entries[0].data).unwrap().2, decode_tga(&rle_tga(0)).unwrap().2);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
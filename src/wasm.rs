@@ -0,0 +1,16 @@
+// JavaScript bindings for converting PNGs in memory. The wasm/ package links them into a module for wasm-pack. The
+// browser has no filesystem, so these go through the in-memory path that the no_std build uses.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::png::{encode_ico_from_bytes, EntryFormat};
+
+/// Converts the bytes of a PNG to the bytes of an ICO with a single PNG entry. From JavaScript, `data` is a
+/// `Uint8Array` and so is the result; errors are thrown as their message.
+#[wasm_bindgen]
+pub fn png_bytes_to_ico(data: &[u8]) -> Result<Vec<u8>, JsValue> {
+	encode_ico_from_bytes(&[data], EntryFormat::Png).map_err(|err| JsValue::from_str(&err.to_string()))
+}
@@ -0,0 +1,58 @@
+// Calls the C interface the way a C caller would, through the declarations in include/pngtoico.h.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+
+use iconifier::png::parse_ico;
+use iconifier::png::testing::rgba_png;
+
+extern "C" {
+	fn pngtoico_convert(input: *const c_char, output: *const c_char) -> c_int;
+	fn pngtoico_last_error() -> *const c_char;
+}
+
+const PNGTOICO_ERROR_INVALID_ARGUMENT: c_int = -1;
+const PNGTOICO_ERROR_UNREADABLE_INPUT: c_int = -2;
+const PNGTOICO_ERROR_INVALID_IMAGE: c_int = -4;
+
+fn c_path(path: &Path) -> CString {
+	CString::new(path.to_str().unwrap()).unwrap()
+}
+
+fn convert(input: &Path, output: &Path) -> c_int {
+	let (input, output) = (c_path(input), c_path(output));
+	unsafe { pngtoico_convert(input.as_ptr(), output.as_ptr()) }
+}
+
+fn last_error() -> Option<String> {
+	let message = unsafe { pngtoico_last_error() };
+	(!message.is_null()).then(|| unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_owned())
+}
+
+#[test]
+fn converts_and_reports_errors() {
+	let dir = std::env::temp_dir().join(format!("iconifier-ffi-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("icon.png"), rgba_png(2, 2, |_, _| [255, 0, 0, 255])).unwrap();
+	std::fs::write(dir.join("text.png"), b"not a png").unwrap();
+
+	assert_eq!(convert(&dir.join("icon.png"), &dir.join("icon.ico")), 0);
+	assert_eq!(last_error(), None);
+	assert_eq!(parse_ico(dir.join("icon.ico")).unwrap()[0].width, 2);
+
+	assert_eq!(convert(&dir.join("missing.png"), &dir.join("missing.ico")), PNGTOICO_ERROR_UNREADABLE_INPUT);
+	assert!(last_error().unwrap().starts_with("Could not read file"));
+	assert_eq!(convert(&dir.join("text.png"), &dir.join("text.ico")), PNGTOICO_ERROR_INVALID_IMAGE);
+	assert!(last_error().unwrap().starts_with("File is not a PNG"));
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_null_arguments() {
+	let output = CString::new("out.ico").unwrap();
+	assert_eq!(unsafe { pngtoico_convert(std::ptr::null(), output.as_ptr()) }, PNGTOICO_ERROR_INVALID_ARGUMENT);
+	assert_eq!(last_error().as_deref(), Some("input is null"));
+	assert_eq!(unsafe { pngtoico_convert(output.as_ptr(), std::ptr::null()) }, PNGTOICO_ERROR_INVALID_ARGUMENT);
+	assert_eq!(last_error().as_deref(), Some("output is null"));
+}
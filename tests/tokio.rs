@@ -0,0 +1,26 @@
+use iconifier::png::testing::rgba_png;
+use iconifier::png::{write_ico, write_ico_async, PngParser};
+
+#[tokio::test]
+async fn converts_a_file_on_a_tokio_runtime() {
+	let dir = std::env::temp_dir().join(format!("iconifier-tokio-{}", std::process::id()));
+	tokio::fs::create_dir_all(&dir).await.unwrap();
+	let png_path = dir.join("icon.png");
+	tokio::fs::write(&png_path, rgba_png(2, 2, |_, _| [255, 0, 0, 255])).await.unwrap();
+
+	let parser = PngParser::new();
+	let metadata = parser.parse_header_async(&png_path).await.unwrap();
+	assert_eq!((metadata.width, metadata.height), (2, 2));
+	write_ico_async(dir.join("async"), metadata, &png_path).await.unwrap();
+	write_ico(dir.join("sync"), metadata, png_path).unwrap();
+
+	let converted = tokio::fs::read(dir.join("async.ico")).await.unwrap();
+	assert_eq!(converted, std::fs::read(dir.join("sync.ico")).unwrap());
+	tokio::fs::remove_dir_all(&dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_missing_files() {
+	let missing = std::env::temp_dir().join("iconifier-tokio-missing.png");
+	assert!(PngParser::new().parse_header_async(&missing).await.is_err());
+}
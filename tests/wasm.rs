@@ -0,0 +1,20 @@
+// Runs in a JavaScript engine with `wasm-pack test --node -- --features wasm,testing`.
+#![cfg(target_arch = "wasm32")]
+
+use iconifier::png::parse_ico_bytes;
+use iconifier::png::testing::rgba_png;
+use iconifier::png_bytes_to_ico;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn converts_png_bytes() {
+	let converted = png_bytes_to_ico(&rgba_png(2, 2, |_, _| [255, 0, 0, 255])).unwrap();
+	let entries = parse_ico_bytes(&converted).unwrap();
+	assert_eq!((entries[0].width, entries[0].height), (2, 2));
+}
+
+#[wasm_bindgen_test]
+fn reports_errors_as_messages() {
+	let error = png_bytes_to_ico(b"not a png").unwrap_err();
+	assert!(error.as_string().is_some_and(|message| !message.is_empty()));
+}
@@ -0,0 +1,2 @@
+// Links the library's `wasm` bindings into a module; wasm-bindgen generates the JavaScript for them.
+pub use iconifier::png_bytes_to_ico;